@@ -1,13 +1,14 @@
 #version 450
 
 layout(location = 0) in vec2 v_tex_coord;
-layout(location = 1) in vec3 tan_light_dir;
-layout(location = 2) in vec3 tan_cam_pos;
-layout(location = 3) in vec3 tan_frag_pos;
+layout(location = 1) in vec3 v_pos;
+layout(location = 2) in mat3 v_tbn;
 
 layout(location = 0) out vec4 f_color;
 
-layout(set = 0, binding = 0) uniform Material {
+layout(set = 0, binding = 0) uniform sampler2D cascade_atlas;
+
+layout(set = 1, binding = 0) uniform Material {
   vec4 ambient;
   vec4 diffuse;
   vec4 specular;
@@ -15,54 +16,150 @@ layout(set = 0, binding = 0) uniform Material {
   vec4 use_texture;
 } material;
 
-layout(set = 0, binding = 1) uniform Model {
+layout(set = 1, binding = 1) uniform Model {
   mat4 model;
 } model;
 
-layout(set = 1, binding = 0) uniform sampler2D diffuse_map;
-layout(set = 1, binding = 1) uniform sampler2D specular_map;
-layout(set = 1, binding = 2) uniform sampler2D normal_map;
+layout(set = 2, binding = 0) uniform sampler2D diffuse_map;
+layout(set = 2, binding = 1) uniform sampler2D specular_map;
+layout(set = 2, binding = 2) uniform sampler2D normal_map;
 
-layout(set = 2, binding = 0) uniform Camera {
+layout(set = 3, binding = 0) uniform Camera {
   mat4 view;
   mat4 proj;
   vec3 pos;
 } camera;
 
-layout(set = 2, binding = 1) uniform Light {
-  vec3 direction;
-  vec3 strength; // vec3 really means float, idk why it doesn't work
-} light;
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
+
+// see CascadeParams in src/shadow/cascades.rs
+layout(set = 3, binding = 2) uniform Cascades {
+  mat4 view_proj[4];
+  vec4 splits;
+  // count, blend fraction, 1 / count, unused
+  vec4 info;
+} cascades;
+
+// 1 if pos is lit in the given cascade, 0 if it's in shadow
+float sample_cascade(int idx, vec3 pos) {
+  vec4 shadow_pos = cascades.view_proj[idx] * vec4(pos, 1.0);
+  vec3 ndc = shadow_pos.xyz / shadow_pos.w;
+  vec2 uv = ndc.xy * 0.5 + 0.5;
+
+  // outside the cascade, nothing there could have cast a shadow
+  if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0 || ndc.z > 1.0) {
+    return 1.0;
+  }
 
-void main() {
-  // only use the texture if we should
-  vec4 tex_diffuse = material.use_texture.r > 0.5 ? texture(diffuse_map, v_tex_coord) : vec4(material.diffuse.rgb, 1.0);
+  // cascades sit side by side in the atlas
+  uv.x = (uv.x + float(idx)) * cascades.info.z;
+  float closest = texture(cascade_atlas, uv).r;
+  float bias = 0.002;
 
-  if (tex_diffuse.a < 0.5) {
-    discard;
+  return ndc.z - bias > closest ? 0.0 : 1.0;
+}
+
+float lit_amount(vec3 pos) {
+  float depth = -(camera.view * vec4(pos, 1.0)).z;
+  int count = int(cascades.info.x);
+
+  int idx = 0;
+  while (idx < count && depth > cascades.splits[idx]) {
+    idx++;
+  }
+  // past the last cascade there are no shadows
+  if (idx == count) {
+    return 1.0;
   }
 
-  vec3 tex_specular = texture(specular_map, v_tex_coord).rgb;
+  float lit = sample_cascade(idx, pos);
+
+  // fade into the next cascade over the end of this one, so there's no line
+  // where the resolution changes
+  float start = idx == 0 ? 0.0 : cascades.splits[idx - 1];
+  float blend_start = mix(cascades.splits[idx], start, cascades.info.y);
+  if (idx + 1 < count && depth > blend_start) {
+    float t = (depth - blend_start) / (cascades.splits[idx] - blend_start);
+    lit = mix(lit, sample_cascade(idx + 1, pos), t);
+  }
 
-  vec3 normal = texture(normal_map, v_tex_coord).rgb * 2.0 - 1.0;
+  return lit;
+}
 
-  // ambient
-  vec3 ambient = tex_diffuse.rgb * 0.01;
+// diffuse and specular from one light, in world space
+vec3 shade(Light light, vec3 normal, vec3 view_dir, vec3 albedo) {
+  vec3 light_dir;
+  float attenuation = 1.0;
+
+  if (light.position.w == 1.0) {
+    // directional
+    light_dir = normalize(light.direction.xyz);
+  } else {
+    vec3 to_light = light.position.xyz - v_pos;
+    float dist = length(to_light);
+    light_dir = to_light / dist;
+
+    // inverse square falloff, windowed so it reaches exactly 0 at the range
+    float window = clamp(1.0 - pow(dist / light.direction.w, 4.0), 0.0, 1.0);
+    attenuation = window * window / (dist * dist + 1.0);
+
+    if (light.position.w == 2.0) {
+      // spot, fades out between the inner and outer cone
+      float cos_angle = dot(-light_dir, light.direction.xyz);
+      attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+    }
+  }
 
-  // diffuse
-  vec3 light_dir = tan_light_dir;
+  // the cascades belong to whichever light has a shadow map
+  if (light.cone.z >= 0.0) {
+    attenuation *= lit_amount(v_pos);
+  }
 
   float diff = max(dot(normal, light_dir), 0.0);
-  vec3 diffuse = diff * tex_diffuse.rgb;
+  vec3 diffuse = diff * albedo;
 
-  // specular
-  vec3 view_dir = normalize(tan_cam_pos - tan_frag_pos);
   vec3 halfway_dir = normalize(light_dir + view_dir);
   float spec = pow(max(dot(normal, halfway_dir), 0.0), material.shininess.r);
   vec3 specular = material.specular.rgb * spec;
 
-  // result
-  vec3 result = ambient + (diffuse + specular) * light.strength.r;
+  return (diffuse + specular) * light.color.rgb * attenuation;
+}
+
+void main() {
+  // only use the texture if we should
+  vec4 tex_diffuse = material.use_texture.r > 0.5 ? texture(diffuse_map, v_tex_coord) : vec4(material.diffuse.rgb, 1.0);
+
+  if (tex_diffuse.a < 0.5) {
+    discard;
+  }
+
+  vec3 tex_specular = texture(specular_map, v_tex_coord).rgb;
+
+  vec3 tan_normal = texture(normal_map, v_tex_coord).rgb * 2.0 - 1.0;
+  vec3 normal = normalize(v_tbn * tan_normal);
+  vec3 view_dir = normalize(camera.pos - v_pos);
+
+  // ambient
+  vec3 result = tex_diffuse.rgb * 0.01;
+
+  for (int i = 0; i < int(lights.count.x); i++) {
+    result += shade(lights.lights[i], normal, view_dir, tex_diffuse.rgb);
+  }
 
   // gamma correction
   float gamma = 2.2;
@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(set = 0, binding = 0) uniform Model {
+  mat4 model;
+} model;
+
+// the cascade's view-projection matrix, from the light's point of view
+layout(set = 0, binding = 1) uniform Cascade {
+  mat4 view_proj;
+} cascade;
+
+void main() {
+  gl_Position = cascade.view_proj * model.model * vec4(position, 1.0);
+}
@@ -6,11 +6,12 @@ layout(location = 2) in vec3 normal;
 layout(location = 3) in vec3 tangent;
 
 layout(location = 0) out vec2 v_tex_coord;
-layout(location = 1) out vec3 tan_light_dir;
-layout(location = 2) out vec3 tan_cam_pos;
-layout(location = 3) out vec3 tan_frag_pos;
+layout(location = 1) out vec3 v_pos;
+layout(location = 2) out mat3 v_tbn;
 
-layout(set = 0, binding = 0) uniform Material {
+layout(set = 0, binding = 0) uniform sampler2D cascade_atlas;
+
+layout(set = 1, binding = 0) uniform Material {
   vec4 ambient;
   vec4 diffuse;
   vec4 specular;
@@ -18,24 +19,36 @@ layout(set = 0, binding = 0) uniform Material {
   vec4 use_texture;
 } material;
 
-layout(set = 0, binding = 1) uniform Model {
+layout(set = 1, binding = 1) uniform Model {
   mat4 model;
 } model;
 
-layout(set = 1, binding = 0) uniform sampler2D diffuse_map;
-layout(set = 1, binding = 1) uniform sampler2D specular_map;
-layout(set = 1, binding = 2) uniform sampler2D normal_map;
+layout(set = 2, binding = 0) uniform sampler2D diffuse_map;
+layout(set = 2, binding = 1) uniform sampler2D specular_map;
+layout(set = 2, binding = 2) uniform sampler2D normal_map;
 
-layout(set = 2, binding = 0) uniform Camera {
+layout(set = 3, binding = 0) uniform Camera {
   mat4 view;
   mat4 proj;
   vec3 pos;
 } camera;
 
-layout(set = 2, binding = 1) uniform Light {
-  vec3 direction;
-  vec3 strength; // vec3 really means float, idk why it doesn't work
-} light;
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
 
 void main() {
   v_tex_coord = tex_coord;
@@ -43,8 +56,7 @@ void main() {
   gl_Position = camera.proj * camera.view * vec4(pos, 1.0);
 
   vec3 bitangent = cross(tangent, normal);
-  mat3 TBN = transpose(mat3(tangent, bitangent, normal));
-  tan_light_dir = normalize(TBN * light.direction);
-  tan_cam_pos = TBN * camera.pos;
-  tan_frag_pos = TBN * pos;
+  // tangent space to world space, lights are all in world space
+  v_tbn = mat3(normalize(tangent), normalize(bitangent), normalize(normal));
+  v_pos = pos;
 }
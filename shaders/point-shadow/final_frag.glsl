@@ -7,9 +7,20 @@ layout(location = 0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform sampler2D shadow_map;
 
-// cube faces +x, -x, +y, -y, +z, -z in a row
+// see CubemapParams in src/shadow/cubemap.rs, from ShadowCubemap::shader_params
+layout(set = 3, binding = 0) uniform ShadowParams {
+  // columns, rows, uv scale, uv offset. the scale and offset keep uvs inside
+  // each face's padding.
+  vec4 cubemap;
+  // near, far, bias in world units, unused
+  vec4 planes;
+} shadow_params;
+
+// cube faces +x, -x, +y, -y, +z, -z, packed the way ShadowCubemap does it.
+// cubemap.xy is the grid's columns and rows, cubemap.z and .w are the scale and
+// offset that keep uvs inside each face's padding.
 // taken from: http://blue2rgb.sydneyzh.com/rendering-dynamic-cube-maps-for-omni-light-shadows-with-vulkan-api.html
-vec2 l_to_shadow_map_uv(vec3 v) {
+vec2 l_to_shadow_map_uv(vec3 v, vec4 cubemap) {
   float face_index;
   vec3 v_abs = abs(v);
   float ma;
@@ -18,34 +29,35 @@ vec2 l_to_shadow_map_uv(vec3 v) {
     {
       face_index = v.z < 0.0 ? 5.0 : 4.0;
       ma = 0.5 / v_abs.z;
-      uv = vec2(v.z < 0.0 ? -v.x : v.x, -v.y);
+      uv = vec2(v.z < 0.0 ? -v.x : v.x, v.y);
     }
   else if(v_abs.y >= v_abs.x)
     {
       face_index = v.y < 0.0 ? 3.0 : 2.0;
       ma = 0.5 / v_abs.y;
-      uv = vec2(v.x, v.y < 0.0 ? -v.z : v.z);
+      uv = vec2(v.x, v.y < 0.0 ? v.z : -v.z);
     }
   else
     {
       face_index = v.x < 0.0 ? 1.0 : 0.0;
       ma = 0.5 / v_abs.x;
-      uv = vec2(v.x < 0.0 ? v.z : -v.z, -v.y);
+      uv = vec2(v.x < 0.0 ? v.z : -v.z, v.y);
     }
   uv = uv * ma + 0.5;
-  uv.x = (uv.x + face_index) / 6.f;
-  return uv;
+  uv = uv * cubemap.z + cubemap.w;
+  vec2 cell = vec2(mod(face_index, cubemap.x), floor(face_index / cubemap.x));
+  return (uv + cell) / cubemap.xy;
 }
 
 bool is_in_shadow() {
   // light is in center
   vec3 light_dir = normalize(v_pos);
-  vec2 coords = l_to_shadow_map_uv(light_dir);
-  float sample_dist = texture(shadow_map, coords).r * 250.0;
+  vec2 coords = l_to_shadow_map_uv(light_dir, shadow_params.cubemap);
+  float sample_dist = mix(shadow_params.planes.x, shadow_params.planes.y, texture(shadow_map, coords).r);
 
   // because light is in center, this works
   float frag_dist = length(v_pos);
-  float bias = 0.005;
+  float bias = shadow_params.planes.z;
 
   // idk why i have to invert it
   return !(sample_dist + bias > frag_dist);
@@ -5,6 +5,7 @@ layout(location = 1) in vec3 tan_light_pos;
 layout(location = 2) in vec3 tan_cam_pos;
 layout(location = 3) in vec3 tan_frag_pos;
 layout(location = 4) in vec3 v_pos;
+layout(location = 5) in mat3 v_tbn;
 
 layout(location = 0) out vec4 f_color;
 
@@ -31,10 +32,47 @@ layout(set = 3, binding = 0) uniform Camera {
   vec3 pos;
 } camera;
 
-layout(set = 3, binding = 1) uniform Light {
-  vec3 position;
-  vec3 strength; // vec3 really means float, idk why it doesn't work
-} light;
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
+
+// see ShadowFilterParams in src/shadow/filter.rs
+layout(set = 3, binding = 2) uniform ShadowFilter {
+  // mode (0 hard, 1 PCF, 2 PCSS, 3 VSM, 4 ESM), sample count, PCF radius in
+  // texels, PCSS light size in texels
+  vec4 mode;
+  // VSM min variance, VSM bleed reduction, ESM exponent, unused
+  vec4 params;
+} shadow_filter;
+
+// see ClusterGridData in src/clusters.rs
+layout(set = 3, binding = 3) uniform ClusterGrid {
+  // clusters in x, y and z, unused
+  vec4 dims;
+  // scale and bias turning log(view depth) into a slice, near, far
+  vec4 depth;
+  // offset << 16 | count for every cluster, four to a uvec4
+  uvec4 grid[768];
+} clusters;
+
+// see ClusterIndexData in src/clusters.rs
+layout(set = 3, binding = 4) uniform ClusterIndices {
+  // light indices, a byte each and sixteen to a uvec4
+  uvec4 indices[512];
+} cluster_indices;
 
 float A = 0.15;
 float B = 0.50;
@@ -50,9 +88,20 @@ vec3 Uncharted2Tonemap(vec3 x)
   return ((x*(A*x+C*B)+D*E)/(x*(A*x+B)+D*F))-E/F;
 }
 
-// cube faces +x, -x, +y, -y, +z, -z in a row
+// see CubemapParams in src/shadow/cubemap.rs, from ShadowCubemap::shader_params
+layout(set = 4, binding = 0) uniform ShadowParams {
+  // columns, rows, uv scale, uv offset. the scale and offset keep uvs inside
+  // each face's padding.
+  vec4 cubemap;
+  // near, far, bias in world units, unused
+  vec4 planes;
+} shadow_params;
+
+// cube faces +x, -x, +y, -y, +z, -z, packed the way ShadowCubemap does it.
+// cubemap.xy is the grid's columns and rows, cubemap.z and .w are the scale and
+// offset that keep uvs inside each face's padding.
 // taken from: http://blue2rgb.sydneyzh.com/rendering-dynamic-cube-maps-for-omni-light-shadows-with-vulkan-api.html
-vec2 l_to_shadow_map_uv(vec3 v) {
+vec2 l_to_shadow_map_uv(vec3 v, vec4 cubemap) {
   float face_index;
   vec3 v_abs = abs(v);
   float ma;
@@ -61,39 +110,229 @@ vec2 l_to_shadow_map_uv(vec3 v) {
     {
       face_index = v.z < 0.0 ? 5.0 : 4.0;
       ma = 0.5 / v_abs.z;
-      uv = vec2(v.z < 0.0 ? -v.x : v.x, -v.y);
+      uv = vec2(v.z < 0.0 ? -v.x : v.x, v.y);
     }
   else if(v_abs.y >= v_abs.x)
     {
       face_index = v.y < 0.0 ? 3.0 : 2.0;
       ma = 0.5 / v_abs.y;
-      uv = vec2(v.x, v.y < 0.0 ? -v.z : v.z);
+      uv = vec2(v.x, v.y < 0.0 ? v.z : -v.z);
     }
   else
     {
       face_index = v.x < 0.0 ? 1.0 : 0.0;
       ma = 0.5 / v_abs.x;
-      uv = vec2(v.x < 0.0 ? v.z : -v.z, -v.y);
+      uv = vec2(v.x < 0.0 ? v.z : -v.z, v.y);
     }
   uv = uv * ma + 0.5;
-  uv = uv * 0.9921875 + 0.00390625;
-  uv.x = (uv.x + face_index) / 6.f;
-  return uv;
+  uv = uv * cubemap.z + cubemap.w;
+  vec2 cell = vec2(mod(face_index, cubemap.x), floor(face_index / cubemap.x));
+  return (uv + cell) / cubemap.xy;
+}
+
+// spread out so that the first 8 or 16 are a decent disk on their own
+const vec2 POISSON_DISK[32] = vec2[](
+  vec2(0.8050, -0.3060),
+  vec2(-0.9617, 0.1971),
+  vec2(0.1115, 0.9699),
+  vec2(-0.3593, -0.9098),
+  vec2(-0.0050, 0.0207),
+  vec2(-0.5964, 0.7939),
+  vec2(0.7158, 0.6262),
+  vec2(0.2854, -0.8171),
+  vec2(-0.7169, -0.4260),
+  vec2(-0.3776, 0.3530),
+  vec2(0.6343, 0.1320),
+  vec2(-0.2088, -0.3924),
+  vec2(0.6448, -0.7271),
+  vec2(0.8980, 0.3455),
+  vec2(0.0943, 0.3385),
+  vec2(0.2844, -0.4934),
+  vec2(-0.2063, 0.9686),
+  vec2(0.9240, -0.0158),
+  vec2(-0.4734, -0.2281),
+  vec2(-0.6586, 0.4847),
+  vec2(-0.7004, 0.0302),
+  vec2(-0.1106, -0.7267),
+  vec2(-0.6035, -0.7129),
+  vec2(-0.9216, -0.1971),
+  vec2(-0.3125, 0.0547),
+  vec2(0.3304, 0.1440),
+  vec2(-0.3216, 0.6658),
+  vec2(0.2202, -0.1810),
+  vec2(0.5236, -0.1967),
+  vec2(0.1805, 0.6762),
+  vec2(0.5042, 0.4116),
+  vec2(0.4891, 0.8247)
+);
+
+float stored_depth(vec3 dir) {
+  return texture(shadow_map, l_to_shadow_map_uv(dir, shadow_params.cubemap)).r;
+}
+
+// fraction of a disk of depth comparisons around dir that pass. offsets are
+// added to the direction rather than the uv, so taps near a face's edge land
+// on the neighbouring face like they should.
+float pcf(vec3 dir, vec3 tangent, vec3 bitangent, float radius, float depth) {
+  // rotate the disk per pixel, which turns banding into noise
+  float angle = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453) * 6.2831853;
+  mat2 rotation = mat2(cos(angle), sin(angle), -sin(angle), cos(angle));
+
+  int samples = int(shadow_filter.mode.y);
+  float lit = 0.0;
+  for (int i = 0; i < samples; i++) {
+    vec2 offset = rotation * POISSON_DISK[i] * radius;
+    lit += depth <= stored_depth(dir + tangent * offset.x + bitangent * offset.y) ? 1.0 : 0.0;
+  }
+
+  return lit / float(samples);
+}
+
+// 1 if pos is lit by the light at light_pos, 0 if it's in shadow. only the
+// light with shadow map 0 has one, the bin renders a single cubemap.
+float lit_amount(vec3 light_pos) {
+  vec3 dir = normalize(v_pos - light_pos);
+  // the shadow map stores distance from the light mapped to 0, 1 between
+  // the near and far planes
+  float near = shadow_params.planes.x;
+  float far = shadow_params.planes.y;
+  float depth = (length(v_pos - light_pos) - near) / (far - near);
+  float biased = depth - shadow_params.planes.z / (far - near);
+  int mode = int(shadow_filter.mode.x);
+
+  if (mode == 3) {
+    // VSM: the chance that the receiver is past the mean occluder, from
+    // Chebyshev's inequality
+    vec2 moments = texture(shadow_map, l_to_shadow_map_uv(dir, shadow_params.cubemap)).rg;
+    if (biased <= moments.x) {
+      return 1.0;
+    }
+    float variance = max(moments.y - moments.x * moments.x, shadow_filter.params.x);
+    float d = biased - moments.x;
+    float p_max = variance / (variance + d * d);
+
+    // everything under the cutoff is shadow, which gets rid of light bleeding
+    // where casters overlap
+    float cutoff = shadow_filter.params.y;
+    return clamp((p_max - cutoff) / (1.0 - cutoff), 0.0, 1.0);
+  }
+
+  if (mode == 4) {
+    // ESM: exp(c * occluder) * exp(-c * receiver) is 1 or more when lit and
+    // falls off quickly behind occluders
+    float occluder = texture(shadow_map, l_to_shadow_map_uv(dir, shadow_params.cubemap)).r;
+    return clamp(occluder * exp(-shadow_filter.params.z * biased), 0.0, 1.0);
+  }
+
+  if (mode == 0) {
+    return biased <= stored_depth(dir) ? 1.0 : 0.0;
+  }
+
+  // the rest filter in the plane facing the light. a texel covers roughly
+  // 2 / face size of it at unit distance.
+  float face_size = float(textureSize(shadow_map, 0).y) / shadow_params.cubemap.y;
+  float texel = 2.0 / face_size;
+  vec3 up = abs(dir.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+  vec3 tangent = normalize(cross(dir, up));
+  vec3 bitangent = cross(dir, tangent);
+
+  float radius = shadow_filter.mode.z;
+  if (mode == 2) {
+    // PCSS: find the average depth of whatever blocks the light, and widen
+    // the filter the further the receiver is behind it
+    float light_size = shadow_filter.mode.w;
+    int samples = int(shadow_filter.mode.y);
+    float blocker_sum = 0.0;
+    float blocker_count = 0.0;
+    for (int i = 0; i < samples; i++) {
+      vec2 offset = POISSON_DISK[i] * light_size * texel;
+      float stored = stored_depth(dir + tangent * offset.x + bitangent * offset.y);
+      if (stored < biased) {
+        blocker_sum += stored;
+        blocker_count += 1.0;
+      }
+    }
+    if (blocker_count == 0.0) {
+      return 1.0;
+    }
+
+    // how far the receiver is behind the blocker, relative to the blocker's
+    // distance from the light
+    float blocker = blocker_sum / blocker_count;
+    float penumbra = (biased - blocker) * (far - near) / mix(near, far, blocker);
+    radius = clamp(light_size * penumbra, 1.0, light_size);
+  }
+
+  return pcf(dir, tangent, bitangent, radius * texel, biased);
+}
+
+// the cluster a world space position is in, see ClusterGrid::assign
+uint cluster_at(vec3 world_pos) {
+  vec4 view_pos = camera.view * vec4(world_pos, 1.0);
+  vec4 clip = camera.proj * view_pos;
+  vec2 ndc = clip.xy / clip.w;
+
+  vec3 dims = clusters.dims.xyz;
+  vec2 tile = clamp(floor((ndc * 0.5 + 0.5) * dims.xy), vec2(0.0), dims.xy - 1.0);
+  float depth = max(-view_pos.z, 0.0001);
+  float slice = clamp(floor(log(depth) * clusters.depth.x + clusters.depth.y), 0.0, dims.z - 1.0);
+
+  return uint(tile.x + dims.x * (tile.y + dims.y * slice));
 }
 
-float shadowedness() {
-  vec3 light_dir = normalize(v_pos - light.position);
-  vec2 coords = l_to_shadow_map_uv(light_dir);
-  float sample_dist = texture(shadow_map, coords).r * 250.0;
+// where the cluster's lights start in cluster_indices, and how many there are
+uvec2 cluster_lights(uint cluster) {
+  uint entry = clusters.grid[cluster / 4u][cluster % 4u];
 
-  float frag_dist = length(v_pos - light.position);
-  float bias = 0.05;
+  return uvec2(entry >> 16u, entry & 0xffffu);
+}
 
-  // idk why i have to invert it
-  float difference = abs(sample_dist - frag_dist);
+uint light_index(uint i) {
+  uint word = cluster_indices.indices[i / 16u][(i / 4u) % 4u];
 
-  return clamp(difference, 0.0, 1.0);
-  /* return !(sample_dist + bias > frag_dist); */
+  return (word >> (8u * (i % 4u))) & 0xffu;
+}
+
+// diffuse and specular from one light, in world space
+vec3 shade(Light light, vec3 normal, vec3 view_dir, vec3 albedo) {
+  vec3 light_dir;
+  float attenuation = 1.0;
+
+  if (light.position.w == 1.0) {
+    // directional
+    light_dir = normalize(light.direction.xyz);
+  } else {
+    vec3 to_light = light.position.xyz - v_pos;
+    float dist = length(to_light);
+    light_dir = to_light / dist;
+
+    // inverse square falloff, windowed so it reaches exactly 0 at the range
+    float window = clamp(1.0 - pow(dist / light.direction.w, 4.0), 0.0, 1.0);
+    attenuation = window * window / (dist * dist + 1.0);
+
+    if (light.position.w == 2.0) {
+      // spot, fades out between the inner and outer cone
+      float cos_angle = dot(-light_dir, light.direction.xyz);
+      attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+    }
+  }
+
+  if (attenuation <= 0.0) {
+    return vec3(0.0);
+  }
+
+  if (light.cone.z >= 0.0) {
+    attenuation *= lit_amount(light.position.xyz);
+  }
+
+  float diff = max(dot(normal, light_dir), 0.0);
+  vec3 diffuse = diff * albedo;
+
+  vec3 halfway_dir = normalize(light_dir + view_dir);
+  float spec = pow(max(dot(normal, halfway_dir), 0.0), 32.0);
+  vec3 specular = vec3(clamp(0.2 * spec, 0.0, 0.5));
+
+  return (diffuse + specular) * light.color.rgb * attenuation;
 }
 
 void main() {
@@ -109,28 +348,18 @@ void main() {
 
   vec3 tex_specular = texture(specular_map, v_tex_coord).rgb;
 
-  vec3 normal = texture(normal_map, v_tex_coord).rgb * 2.0 - 1.0;
+  vec3 tan_normal = texture(normal_map, v_tex_coord).rgb * 2.0 - 1.0;
+  vec3 normal = normalize(v_tbn * tan_normal);
+  vec3 view_dir = normalize(camera.pos - v_pos);
 
   // ambient
-  vec3 ambient = tex_diffuse.rgb * 0.01;
+  vec3 result = tex_diffuse.rgb * 0.01;
 
-  // diffuse
-  vec3 light_dir = normalize(tan_light_pos - tan_frag_pos);
-
-  float diff = max(dot(normal, light_dir), 0.0);
-  vec3 diffuse = diff * tex_diffuse.rgb;
-
-  // specular
-  vec3 view_dir = normalize(tan_cam_pos - tan_frag_pos);
-  vec3 halfway_dir = normalize(light_dir + view_dir);
-  float spec = pow(max(dot(normal, halfway_dir), 0.0), 32.0);
-  vec3 specular = vec3(clamp(0.2 * spec, 0.0, 0.5));
-
-  // result
-  float dist = length(tan_light_pos - tan_frag_pos);
-  float shadow = shadowedness();
-
-  vec3 result = ambient + (1.0 - shadow) * (diffuse + specular) * light.strength.r / (dist * dist / 2000.0);
+  // only the lights that can reach this fragment's cluster
+  uvec2 range = cluster_lights(cluster_at(v_pos));
+  for (uint i = range.x; i < range.x + range.y; i++) {
+    result += shade(lights.lights[light_index(i)], normal, view_dir, tex_diffuse.rgb);
+  }
 
   // uncharted 2 tone mapping
   result *= 16;
@@ -31,10 +31,22 @@ layout(set = 3, binding = 0) uniform Camera {
   vec3 pos;
 } camera;
 
-layout(set = 3, binding = 1) uniform Light {
-  vec3 position;
-  vec3 strength; // vec3 really means float, idk why it doesn't work
-} light;
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
 
 float A = 0.15;
 float B = 0.50;
@@ -50,9 +62,20 @@ vec3 Uncharted2Tonemap(vec3 x)
   return ((x*(A*x+C*B)+D*E)/(x*(A*x+B)+D*F))-E/F;
 }
 
-// cube faces +x, -x, +y, -y, +z, -z in a row
+// see CubemapParams in src/shadow/cubemap.rs, from ShadowCubemap::shader_params
+layout(set = 4, binding = 0) uniform ShadowParams {
+  // columns, rows, uv scale, uv offset. the scale and offset keep uvs inside
+  // each face's padding.
+  vec4 cubemap;
+  // near, far, bias in world units, unused
+  vec4 planes;
+} shadow_params;
+
+// cube faces +x, -x, +y, -y, +z, -z, packed the way ShadowCubemap does it.
+// cubemap.xy is the grid's columns and rows, cubemap.z and .w are the scale and
+// offset that keep uvs inside each face's padding.
 // taken from: http://blue2rgb.sydneyzh.com/rendering-dynamic-cube-maps-for-omni-light-shadows-with-vulkan-api.html
-vec2 l_to_shadow_map_uv(vec3 v) {
+vec2 l_to_shadow_map_uv(vec3 v, vec4 cubemap) {
   float face_index;
   vec3 v_abs = abs(v);
   float ma;
@@ -61,33 +84,33 @@ vec2 l_to_shadow_map_uv(vec3 v) {
     {
       face_index = v.z < 0.0 ? 5.0 : 4.0;
       ma = 0.5 / v_abs.z;
-      uv = vec2(v.z < 0.0 ? -v.x : v.x, -v.y);
+      uv = vec2(v.z < 0.0 ? -v.x : v.x, v.y);
     }
   else if(v_abs.y >= v_abs.x)
     {
       face_index = v.y < 0.0 ? 3.0 : 2.0;
       ma = 0.5 / v_abs.y;
-      uv = vec2(v.x, v.y < 0.0 ? -v.z : v.z);
+      uv = vec2(v.x, v.y < 0.0 ? v.z : -v.z);
     }
   else
     {
       face_index = v.x < 0.0 ? 1.0 : 0.0;
       ma = 0.5 / v_abs.x;
-      uv = vec2(v.x < 0.0 ? v.z : -v.z, -v.y);
+      uv = vec2(v.x < 0.0 ? v.z : -v.z, v.y);
     }
   uv = uv * ma + 0.5;
-  uv = uv * 0.9921875 + 0.00390625;
-  uv.x = (uv.x + face_index) / 6.f;
-  return uv;
+  uv = uv * cubemap.z + cubemap.w;
+  vec2 cell = vec2(mod(face_index, cubemap.x), floor(face_index / cubemap.x));
+  return (uv + cell) / cubemap.xy;
 }
 
 float shadowedness() {
-  vec3 light_dir = normalize(v_pos - light.position);
-  vec2 coords = l_to_shadow_map_uv(light_dir);
-  float sample_dist = texture(shadow_map, coords).r * 250.0;
+  vec3 light_dir = normalize(v_pos - lights.lights[0].position.xyz);
+  vec2 coords = l_to_shadow_map_uv(light_dir, shadow_params.cubemap);
+  float sample_dist = mix(shadow_params.planes.x, shadow_params.planes.y, texture(shadow_map, coords).r);
 
-  float frag_dist = length(v_pos - light.position);
-  float bias = 0.05;
+  float frag_dist = length(v_pos - lights.lights[0].position.xyz);
+  float bias = shadow_params.planes.z;
 
   // idk why i have to invert it
   float difference = abs(sample_dist - frag_dist);
@@ -128,12 +151,12 @@ void main() {
   vec3 specular = vec3(clamp(0.2 * spec, 0.0, 0.5));
 
   // result
-  /* vec3 result = ambient + (diffuse + specular) * light.strength.r; */
+  /* vec3 result = ambient + (diffuse + specular) * lights.lights[0].color.r; */
   float dist = length(tan_light_pos - tan_frag_pos);
   float shadow = shadowedness();
   /* float shadow = 0.0; */
 
-  vec3 result = ambient + (1.0 - shadow) * (diffuse + specular) * light.strength.r / (dist * dist / 2000.0);
+  vec3 result = ambient + (1.0 - shadow) * (diffuse + specular) * lights.lights[0].color.r / (dist * dist);
 
   // gamma correction and reinhard
   /*
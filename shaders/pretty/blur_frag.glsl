@@ -2,17 +2,25 @@
 
 layout(location = 0) in vec2 v_pos;
 
-layout(set = 0, binding = 0) uniform sampler2D depth_map;
+layout(location = 0) out vec4 f_moments;
+
+// VSM moments or ESM exponentials, unlike depths these can be averaged
+layout(set = 0, binding = 0) uniform sampler2D moments_map;
+
+// 5x5 gaussian, sigma 1
+const float WEIGHTS[3] = float[](0.4026, 0.2442, 0.0545);
 
 void main() {
-  float depth = 0.0;
-  float radius = 0.0002;
+  // 2 texels either way, which stays inside the cubemap's padding
+  vec2 texel = 1.0 / vec2(textureSize(moments_map, 0));
+
+  vec4 moments = vec4(0.0);
   for (int x = -2; x <= 2; x++) {
     for (int y = -2; y <= 2; y++) {
-      vec2 tex_coords = v_pos.xy + vec2(x * radius, y * radius);
-      float sample_depth = texture(depth_map, tex_coords).r;
-      depth += sample_depth;
+      vec2 tex_coords = v_pos.xy + vec2(x, y) * texel;
+      moments += texture(moments_map, tex_coords) * WEIGHTS[abs(x)] * WEIGHTS[abs(y)];
     }
   }
-  gl_FragDepth = depth / 25.0;
+
+  f_moments = moments;
 }
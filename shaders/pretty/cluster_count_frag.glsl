@@ -0,0 +1,109 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coord;
+layout(location = 1) in vec3 tan_light_pos;
+layout(location = 2) in vec3 tan_cam_pos;
+layout(location = 3) in vec3 tan_frag_pos;
+layout(location = 4) in vec3 v_pos;
+layout(location = 5) in mat3 v_tbn;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D shadow_map;
+layout(set = 1, binding = 0) uniform Material {
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+  vec3 shininess;
+  vec3 use_texture;
+} material;
+
+layout(set = 1, binding = 1) uniform Model {
+  mat4 model;
+} model;
+
+layout(set = 2, binding = 0) uniform sampler2D diffuse_map;
+layout(set = 2, binding = 1) uniform sampler2D specular_map;
+layout(set = 2, binding = 2) uniform sampler2D normal_map;
+
+layout(set = 3, binding = 0) uniform Camera {
+  mat4 view;
+  mat4 proj;
+  vec3 pos;
+} camera;
+
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
+
+// see ClusterGridData in src/clusters.rs
+layout(set = 3, binding = 3) uniform ClusterGrid {
+  // clusters in x, y and z, unused
+  vec4 dims;
+  // scale and bias turning log(view depth) into a slice, near, far
+  vec4 depth;
+  // offset << 16 | count for every cluster, four to a uvec4
+  uvec4 grid[768];
+} clusters;
+
+// see ClusterIndexData in src/clusters.rs
+layout(set = 3, binding = 4) uniform ClusterIndices {
+  // light indices, a byte each and sixteen to a uvec4
+  uvec4 indices[512];
+} cluster_indices;
+
+// the cluster a world space position is in, see ClusterGrid::assign
+uint cluster_at(vec3 world_pos) {
+  vec4 view_pos = camera.view * vec4(world_pos, 1.0);
+  vec4 clip = camera.proj * view_pos;
+  vec2 ndc = clip.xy / clip.w;
+
+  vec3 dims = clusters.dims.xyz;
+  vec2 tile = clamp(floor((ndc * 0.5 + 0.5) * dims.xy), vec2(0.0), dims.xy - 1.0);
+  float depth = max(-view_pos.z, 0.0001);
+  float slice = clamp(floor(log(depth) * clusters.depth.x + clusters.depth.y), 0.0, dims.z - 1.0);
+
+  return uint(tile.x + dims.x * (tile.y + dims.y * slice));
+}
+
+// where the cluster's lights start in cluster_indices, and how many there are
+uvec2 cluster_lights(uint cluster) {
+  uint entry = clusters.grid[cluster / 4u][cluster % 4u];
+
+  return uvec2(entry >> 16u, entry & 0xffffu);
+}
+
+uint light_index(uint i) {
+  uint word = cluster_indices.indices[i / 16u][(i / 4u) % 4u];
+
+  return (word >> (8u * (i % 4u))) & 0xffu;
+}
+
+// blue for one light, through green and yellow to red for 8 or more
+vec3 heat(float t) {
+  return clamp(vec3(1.5 - abs(4.0 * t - vec3(3.0, 2.0, 1.0))), 0.0, 1.0);
+}
+
+void main() {
+  // how many lights this fragment's cluster has to shade, over a faint copy
+  // of the scene so it's clear where things are
+  uint count = cluster_lights(cluster_at(v_pos)).y;
+  vec3 albedo = material.use_texture.r > 0.5 ? texture(diffuse_map, v_tex_coord).rgb : material.diffuse;
+
+  vec3 color = count == 0 ? vec3(0.0) : heat(float(count - 1) / 7.0);
+
+  f_color = vec4(mix(color, albedo, 0.2), 1.0);
+}
@@ -31,10 +31,22 @@ layout(set = 3, binding = 0) uniform Camera {
   vec3 pos;
 } camera;
 
-layout(set = 3, binding = 1) uniform Light {
-  vec3 position;
-  vec3 strength; // vec3 really means float, idk why it doesn't work
-} light;
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
 
 void main() {
   // only use the texture if we should
@@ -62,7 +74,7 @@ void main() {
 
   // result
   float dist = length(tan_light_pos - tan_frag_pos);
-  vec3 result = ambient + (diffuse + specular) * light.strength.r / (dist * dist / 2000.0);
+  vec3 result = ambient + (diffuse + specular) * lights.lights[0].color.r / (dist * dist);
 
   vec3 corrected = pow(result, vec3(1/2.2));
 
@@ -7,11 +7,22 @@ layout(set = 3, binding = 0) uniform Light {
   vec3 strength;
 } light;
 
+// see CubemapParams in src/shadow/cubemap.rs, from ShadowCubemap::shader_params
+layout(set = 3, binding = 1) uniform ShadowParams {
+  // columns, rows, uv scale, uv offset. the scale and offset keep uvs inside
+  // each face's padding.
+  vec4 cubemap;
+  // near, far, bias in world units, unused
+  vec4 planes;
+} shadow_params;
+
 void main() {
   float light_dist = length(v_pos - light.position);
 
-  // map to 0, 1 by dividing by far plane
-  light_dist /= 250.0;
+  // map to 0, 1 between the near and far planes, like the projection does
+  float near = shadow_params.planes.x;
+  float far = shadow_params.planes.y;
+  light_dist = (light_dist - near) / (far - near);
 
   gl_FragDepth = light_dist;
 }
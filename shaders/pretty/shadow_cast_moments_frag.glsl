@@ -0,0 +1,46 @@
+#version 450
+
+layout(location = 0) in vec3 v_pos;
+
+layout(location = 0) out vec4 f_moments;
+
+layout(set = 3, binding = 0) uniform Light {
+  vec3 position;
+  vec3 strength;
+} light;
+
+// see ShadowFilterParams in src/shadow/filter.rs
+layout(set = 3, binding = 1) uniform ShadowFilter {
+  vec4 mode;
+  vec4 params;
+} shadow_filter;
+
+// see CubemapParams in src/shadow/cubemap.rs, from ShadowCubemap::shader_params
+layout(set = 3, binding = 2) uniform ShadowParams {
+  // columns, rows, uv scale, uv offset. the scale and offset keep uvs inside
+  // each face's padding.
+  vec4 cubemap;
+  // near, far, bias in world units, unused
+  vec4 planes;
+} shadow_params;
+
+void main() {
+  // map to 0, 1 between the near and far planes, like the projection does
+  float near = shadow_params.planes.x;
+  float far = shadow_params.planes.y;
+  float depth = (length(v_pos - light.position) - near) / (far - near);
+
+  // the depth buffer only keeps the nearest caster
+  gl_FragDepth = depth;
+
+  if (int(shadow_filter.mode.x) == 4) {
+    // ESM
+    f_moments = vec4(exp(shadow_filter.params.z * depth), 0.0, 0.0, 1.0);
+  } else {
+    // VSM. the derivatives account for the depth changing across the texel,
+    // which keeps sloped surfaces from shadowing themselves.
+    float dx = dFdx(depth);
+    float dy = dFdy(depth);
+    f_moments = vec4(depth, depth * depth + 0.25 * (dx * dx + dy * dy), 0.0, 1.0);
+  }
+}
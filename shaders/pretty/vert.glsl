@@ -10,6 +10,7 @@ layout(location = 1) out vec3 tan_light_pos;
 layout(location = 2) out vec3 tan_cam_pos;
 layout(location = 3) out vec3 tan_frag_pos;
 layout(location = 4) out vec3 v_pos;
+layout(location = 5) out mat3 v_tbn;
 
 layout(set = 1, binding = 0) uniform Material {
   vec3 ambient;
@@ -33,10 +34,56 @@ layout(set = 3, binding = 0) uniform Camera {
   vec3 pos;
 } camera;
 
-layout(set = 3, binding = 1) uniform Light {
-  vec3 position;
-  vec3 strength; // vec3 really means float, idk why it doesn't work
-} light;
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 3, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
+
+// see ShadowFilterParams in src/shadow/filter.rs
+layout(set = 3, binding = 2) uniform ShadowFilter {
+  // mode (0 hard, 1 PCF, 2 PCSS, 3 VSM, 4 ESM), sample count, PCF radius in
+  // texels, PCSS light size in texels
+  vec4 mode;
+  // VSM min variance, VSM bleed reduction, ESM exponent, unused
+  vec4 params;
+} shadow_filter;
+
+// see ClusterGridData in src/clusters.rs
+layout(set = 3, binding = 3) uniform ClusterGrid {
+  // clusters in x, y and z, unused
+  vec4 dims;
+  // scale and bias turning log(view depth) into a slice, near, far
+  vec4 depth;
+  // offset << 16 | count for every cluster, four to a uvec4
+  uvec4 grid[768];
+} clusters;
+
+// see ClusterIndexData in src/clusters.rs
+layout(set = 3, binding = 4) uniform ClusterIndices {
+  // light indices, a byte each and sixteen to a uvec4
+  uvec4 indices[512];
+} cluster_indices;
+
+// see CubemapParams in src/shadow/cubemap.rs, from ShadowCubemap::shader_params
+layout(set = 4, binding = 0) uniform ShadowParams {
+  // columns, rows, uv scale, uv offset. the scale and offset keep uvs inside
+  // each face's padding.
+  vec4 cubemap;
+  // near, far, bias in world units, unused
+  vec4 planes;
+} shadow_params;
 
 void main() {
   v_tex_coord = tex_coord;
@@ -45,7 +92,11 @@ void main() {
 
   vec3 bitangent = cross(tangent, normal);
   mat3 TBN = transpose(mat3(normalize(tangent), normalize(bitangent), normalize(normal)));
-  tan_light_pos = TBN * light.position;
+  // the single-light debug shaders only look at the first light
+  tan_light_pos = TBN * lights.lights[0].position.xyz;
   tan_cam_pos = TBN * camera.pos;
   tan_frag_pos = TBN * v_pos;
+
+  // tangent space to world space, for shaders that light in world space
+  v_tbn = transpose(TBN);
 }
@@ -0,0 +1,109 @@
+#version 450
+
+layout(location = 0) in vec3 v_pos;
+layout(location = 1) in vec3 v_normal;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D spot_shadows;
+
+layout(set = 1, binding = 0) uniform Model {
+  mat4 model;
+} model;
+
+layout(set = 2, binding = 0) uniform Camera {
+  mat4 view;
+  mat4 proj;
+  vec3 pos;
+} camera;
+
+// see LightData in src/light.rs
+struct Light {
+  // w is the kind: 0 point, 1 directional, 2 spot
+  vec4 position;
+  // w is the range
+  vec4 direction;
+  vec4 color;
+  // cos(inner angle), cos(outer angle), shadow map index or -1
+  vec4 cone;
+};
+
+layout(set = 2, binding = 1) uniform Lights {
+  // x is the light count
+  vec4 count;
+  Light lights[32];
+} lights;
+
+// see SpotShadowParams in src/shadow/spot.rs
+layout(set = 2, binding = 2) uniform SpotShadows {
+  mat4 view_proj[4];
+  // where each light's patch is: atlas uv = uv * xy + zw
+  vec4 uv_transform[4];
+  // x is the light count
+  vec4 info;
+} spot_shadow_params;
+
+// 1 if pos is lit by the light using shadow map idx, 0 if something's in the
+// way
+float spot_lit_amount(int idx, vec3 light_pos, vec3 pos, vec3 normal) {
+  if (idx < 0 || float(idx) >= spot_shadow_params.info.x) {
+    return 1.0;
+  }
+
+  vec4 shadow_pos = spot_shadow_params.view_proj[idx] * vec4(pos, 1.0);
+  vec3 ndc = shadow_pos.xyz / shadow_pos.w;
+  vec2 uv = ndc.xy * 0.5 + 0.5;
+
+  if (shadow_pos.w <= 0.0 || uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+    return 1.0;
+  }
+
+  // every light has its own patch somewhere in the texture
+  vec4 patch = spot_shadow_params.uv_transform[idx];
+  uv = uv * patch.xy + patch.zw;
+  float closest = texture(spot_shadows, uv).r;
+
+  // depth isn't linear with a perspective projection, so the bias has to be
+  // tiny. surfaces facing away from the light need a bit more.
+  vec3 to_light = normalize(light_pos - pos);
+  float bias = mix(0.00002, 0.0002, 1.0 - max(dot(normal, to_light), 0.0));
+
+  return ndc.z - bias > closest ? 0.0 : 1.0;
+}
+
+vec3 spot_light(Light light, vec3 pos, vec3 normal) {
+  vec3 to_light = light.position.xyz - pos;
+  float dist = length(to_light);
+  to_light /= dist;
+
+  // full strength inside the inner cone, fading smoothly to nothing at the
+  // outer one
+  float cos_angle = dot(-to_light, light.direction.xyz);
+  float cone = smoothstep(light.cone.y, light.cone.x, cos_angle);
+
+  // inverse square falloff, windowed so it reaches exactly 0 at the range
+  float window = clamp(1.0 - pow(dist / light.direction.w, 4.0), 0.0, 1.0);
+  float attenuation = window * window / (dist * dist + 1.0);
+
+  float diff = max(dot(normal, to_light), 0.0);
+
+  return light.color.rgb * diff * cone * attenuation;
+}
+
+void main() {
+  vec3 normal = normalize(v_normal);
+  vec3 albedo = vec3(0.8);
+
+  vec3 result = albedo * 0.02;
+  // the demo only has spot lights
+  for (int i = 0; i < int(lights.count.x); i++) {
+    Light light = lights.lights[i];
+    result += albedo * spot_light(light, v_pos, normal)
+      * spot_lit_amount(int(light.cone.z), light.position.xyz, v_pos, normal);
+  }
+
+  // gamma correction
+  result = pow(result, vec3(1.0 / 2.2));
+
+  f_color = vec4(result, 1.0);
+}
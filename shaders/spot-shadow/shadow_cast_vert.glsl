@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(set = 0, binding = 0) uniform Model {
+  mat4 model;
+} model;
+
+// the light's view-projection matrix
+layout(set = 0, binding = 1) uniform LightMatrix {
+  mat4 view_proj;
+} light_matrix;
+
+void main() {
+  gl_Position = light_matrix.view_proj * model.model * vec4(position, 1.0);
+}
@@ -1,3 +1,4 @@
+use render_engine::input::VirtualKeyCode;
 use render_engine::mesh::PrimitiveTopology;
 use render_engine::render_passes;
 use render_engine::system::{Pass, System};
@@ -10,7 +11,7 @@ use nalgebra_glm::*;
 use std::collections::HashMap;
 
 use tests_render_engine::mesh::{convert_meshes, load_obj};
-use tests_render_engine::{relative_path, OrbitCamera, Matrix4};
+use tests_render_engine::{relative_path, Camera, OrbitCamera, Matrix4};
 
 fn main() {
     // initialize window
@@ -48,7 +49,9 @@ fn main() {
     // load, create pipeline spec and set for model matrix
     // only load 1st object
     let (mut models, _materials) = load_obj(&relative_path("meshes/dragon.obj")).expect("couldn't load OBJ");
-    let mesh = convert_meshes(&[models.remove(0)]).remove(0);
+    let mesh = convert_meshes(&[models.remove(0)])
+        .expect("couldn't convert mesh")
+        .remove(0);
     dbg!["done loading meshes"];
 
     let mut object = ObjectPrototype {
@@ -71,7 +74,12 @@ fn main() {
     while !window.update() {
         // update camera and camera buffer
         camera_timer.start();
-        camera.update(window.get_frame_info());
+        let frame_info = window.get_frame_info();
+        // F brings the dragon back to the middle after panning away
+        if frame_info.keydowns.contains(&VirtualKeyCode::F) {
+            camera.focus_on(vec3(0.0, 0.0, 0.0));
+        }
+        camera.update(frame_info);
 
         let camera_data = camera.get_data();
 
@@ -14,7 +14,7 @@ use vulkano::format::Format;
 use std::collections::HashMap;
 
 use tests_render_engine::mesh::{add_tangents, convert_meshes, load_obj};
-use tests_render_engine::{relative_path, OrbitCamera, Matrix4};
+use tests_render_engine::{relative_path, Camera, OrbitCamera, Matrix4};
 
 fn main() {
     // initialize window
@@ -81,7 +81,9 @@ fn main() {
     // load mesh and create object
     let (mut models, _materials) =
         load_obj(&relative_path("meshes/raptor.obj")).expect("couldn't load OBJ");
-    let basic_mesh = convert_meshes(&[models.remove(0)]).remove(0);
+    let basic_mesh = convert_meshes(&[models.remove(0)])
+        .expect("couldn't convert mesh")
+        .remove(0);
     let mesh = add_tangents(&basic_mesh);
 
     let mut object = ObjectPrototype {
@@ -23,7 +23,7 @@ use std::collections::HashMap;
 use tests_render_engine::mesh::{
     add_tangents, convert_meshes, load_obj, merge, only_pos_from_ptnt, wireframe, VPosTexNormTan,
 };
-use tests_render_engine::{relative_path, OrbitCamera, Matrix4};
+use tests_render_engine::{relative_path, Camera, OrbitCamera, Matrix4};
 
 fn main() {
     // initialize window
@@ -77,7 +77,9 @@ fn main() {
     // load meshes and create objects
     let (mut models, _materials) =
         load_obj(&relative_path("meshes/raptor.obj")).expect("Couldn't load OBJ file");
-    let basic_mesh = convert_meshes(&[models.remove(0)]).remove(0);
+    let basic_mesh = convert_meshes(&[models.remove(0)])
+        .expect("Couldn't convert mesh")
+        .remove(0);
     let raptor_mesh = add_tangents(&basic_mesh);
     let normals_mesh = normals_vis(&raptor_mesh);
 
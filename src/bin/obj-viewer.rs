@@ -1,11 +1,11 @@
 use render_engine::collection::{Data, Set};
-use render_engine::input::get_elapsed;
-use render_engine::mesh::PrimitiveTopology;
+use render_engine::input::VirtualKeyCode;
+use render_engine::mesh::{Mesh, PrimitiveTopology};
 use render_engine::object::{Object, ObjectPrototype};
 use render_engine::render_passes;
 use render_engine::system::{Pass, System};
 use render_engine::window::Window;
-use render_engine::Image;
+use render_engine::{Format, Image, Queue, RenderPass};
 
 use std::collections::HashMap;
 use std::env;
@@ -13,11 +13,21 @@ use std::path::Path;
 
 use nalgebra_glm::{scale, vec3, Mat4};
 
-use tests_render_engine::mesh::{add_tangents_multi, convert_meshes, load_obj, load_textures};
-use tests_render_engine::{relative_path, CameraData, FlyCamera, Matrix4};
+use tests_render_engine::camera::aspect_ratio;
+use tests_render_engine::camera_path::{CameraPath, PathCamera};
+use tests_render_engine::error::LoadError;
+use tests_render_engine::light::{DirectionalLight, Light, LightListData};
+use tests_render_engine::light_animation::{sample_lights, AnimatedLight, AnimationClock, Motion};
+use tests_render_engine::mesh::{
+    add_tangents_multi, convert_meshes_with, load_obj, load_textures_reported, merge,
+    only_pos_from_ptnt, ConvertOptions, VPos,
+};
+use tests_render_engine::report::LoadReport;
+use tests_render_engine::shadow::{CascadeParams, CascadedShadowMap};
+use tests_render_engine::{relative_path, Camera, CameraData, FlyCamera, Matrix4};
 
 fn main() {
-    // get path to load_obj
+    // get path to load_obj, and optionally a camera path to play with P
     let args: Vec<String> = env::args().collect();
     let path = if args.len() < 2 {
         println!("No path given to load!");
@@ -25,45 +35,235 @@ fn main() {
     } else {
         Path::new(&args[1])
     };
+    let camera_path_file = args
+        .iter()
+        .position(|arg| arg == "--path")
+        .and_then(|idx| args.get(idx + 1))
+        .map(Path::new);
 
     // initialize window
     let (mut window, queue) = Window::new();
     let device = queue.device().clone();
 
     // create system
+    let csm = CascadedShadowMap::default();
+    let cascade_atlas: Image = vulkano::image::AttachmentImage::sampled(
+        device.clone(),
+        csm.dimensions(),
+        Format::D32Sfloat,
+    )
+    .unwrap();
+    let mut custom_images = HashMap::new();
+    custom_images.insert("cascade_atlas", cascade_atlas);
+
+    let rpass_shadow = render_passes::only_depth(device.clone());
     let render_pass = render_passes::multisampled_with_depth(device.clone(), 4);
     let mut system = System::new(
         queue.clone(),
-        vec![Pass {
-            name: "geometry",
-            images_created_tags: vec![
-                "resolve_color",
-                "multisampled_color",
-                "multisampled_depth",
-            ],
-            images_needed_tags: vec![],
-            render_pass: render_pass.clone(),
-        }],
-        // custom images, we use none
-        HashMap::new(),
+        vec![
+            // renders every cascade's shadow map, side by side
+            Pass {
+                name: "shadow",
+                images_created_tags: vec!["cascade_atlas"],
+                images_needed_tags: vec![],
+                render_pass: rpass_shadow.clone(),
+            },
+            Pass {
+                name: "geometry",
+                images_created_tags: vec![
+                    "resolve_color",
+                    "multisampled_color",
+                    "multisampled_depth",
+                ],
+                images_needed_tags: vec!["cascade_atlas"],
+                render_pass: render_pass.clone(),
+            },
+        ],
+        custom_images,
         "resolve_color",
     );
 
     window.set_render_pass(render_pass.clone());
 
-    // initialize camera
-    let mut camera = FlyCamera::default();
+    // initialize camera. models can be any size, so use a near plane that
+    // won't clip small ones
+    let mut camera = FlyCamera::default().with_clip_planes(0.01, 1_000.0);
+
+    // a sun circling overhead, with the cascades as its shadow map. L pauses
+    // it, [ and ] scrub through its animation.
+    let sun = AnimatedLight::new(DirectionalLight::new(vec3(0.0, 2.0, 1.0)))
+        .with_motion(Motion::Orbit {
+            center: vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+            height: 2.0,
+            period: -8.0 * std::f32::consts::PI,
+            phase: std::f32::consts::FRAC_PI_2,
+        })
+        .with_shadow(0);
+    let mut light_clock = AnimationClock::new();
+    let light_data = sample_lights(&[sun.clone()], light_clock.time).get_data();
+
+    // load meshes and materials. if that fails, say why and show an empty
+    // scene instead of crashing
+    let (mut objects, caster_mesh) = match load_objects(
+        queue.clone(),
+        render_pass.clone(),
+        path,
+        &camera.get_data(),
+        &light_data,
+        &csm.shader_params(&[]),
+    ) {
+        Ok((objects, caster_mesh)) => (objects, Some(caster_mesh)),
+        Err(e) => {
+            println!("Couldn't load {:?}: {}", path, e);
+            (vec![], None)
+        }
+    };
+
+    println!("Objects Loaded: {}", objects.len());
+
+    // one shadow caster per cascade, all of the scene merged into one mesh.
+    // each one only draws to its cascade's part of the atlas.
+    let model_mat: Matrix4 = Mat4::identity().into();
+    let mut casters: Vec<CasterObject> = caster_mesh
+        .iter()
+        .flat_map(|caster_mesh| (0..csm.cascade_count).map(move |idx| (idx, caster_mesh)))
+        .map(|(idx, caster_mesh)| {
+            ObjectPrototype {
+                vs_path: relative_path("shaders/obj-viewer/shadow_cast_vert.glsl"),
+                fs_path: relative_path("shaders/obj-viewer/shadow_cast_frag.glsl"),
+                fill_type: PrimitiveTopology::TriangleList,
+                read_depth: true,
+                write_depth: true,
+                mesh: caster_mesh.clone(),
+                // the cascade matrix is filled in every frame
+                collection: ((model_mat, model_mat),),
+                custom_dynamic_state: Some(csm.cascade_viewport(idx)),
+            }
+            .build(queue.clone(), rpass_shadow.clone())
+        })
+        .collect();
+
+    let mut path_camera: Option<PathCamera> = None;
+
+    // used in main loop
+    while !window.update() {
+        // P starts or stops the camera path
+        if window
+            .get_frame_info()
+            .keydowns
+            .contains(&VirtualKeyCode::P)
+        {
+            path_camera = match (path_camera, camera_path_file) {
+                (Some(_), _) => None,
+                (None, Some(file)) => match CameraPath::load(file) {
+                    Ok(path) => Some(PathCamera::new(path).with_projection(camera.projection)),
+                    Err(e) => {
+                        println!("Couldn't load camera path: {}", e);
+                        None
+                    }
+                },
+                (None, None) => {
+                    println!("No camera path given, pass one with --path");
+                    None
+                }
+            };
+        }
+
+        // get updated info on camera and light. the cascades need the
+        // camera's view and projection separately, not just its matrices.
+        let (camera_data, camera_view, projection) = if let Some(path_cam) = path_camera.as_mut()
+        {
+            path_cam.update(window.get_frame_info());
+            (path_cam.get_data(), path_cam.view_matrix(), path_cam.projection)
+        } else {
+            camera.update(window.get_frame_info());
+            (camera.get_data(), camera.view_matrix(), camera.projection)
+        };
+        if path_camera.as_ref().map(|cam| cam.is_finished()) == Some(true) {
+            path_camera = None;
+        }
+        light_clock.update(&window.get_frame_info());
+        let lights = sample_lights(&[sun.clone()], light_clock.time);
+        let light_data = lights.get_data();
+        let sun_direction = match lights.lights().next() {
+            Some(Light::Directional(sun)) => sun.direction,
+            _ => vec3(0.0, 1.0, 0.0),
+        };
+
+        // fit the cascades to what the camera sees now
+        let cascades = csm.cascades(
+            &camera_view,
+            &projection,
+            aspect_ratio(window.get_frame_info().dimensions),
+            &sun_direction,
+        );
+        let cascade_params = csm.shader_params(&cascades);
+        for (caster, cascade) in casters.iter_mut().zip(cascades.iter()) {
+            caster.collection.0.data.1 = cascade.view_projection().into();
+            caster.collection.0.upload(device.clone());
+        }
+
+        // update collections
+        objects.iter_mut().for_each(|obj| {
+            obj.collection.2.data.0 = camera_data.clone();
+            obj.collection.2.data.1 = light_data;
+            obj.collection.2.data.2 = cascade_params;
+            obj.collection.2.upload(device.clone());
+        });
+
+        // draw
+        system.start_window(&mut window);
+
+        for caster in casters.iter() {
+            system.add_object(caster);
+        }
+
+        system.next_pass();
+
+        for object in objects.iter() {
+            system.add_object(object);
+        }
+
+        system.finish_to_window(&mut window);
+    }
 
-    // light
-    let moving_light = MovingLight::new();
-    let light_data = moving_light.get_data();
+    println!("FPS: {}", window.get_fps());
+}
 
-    // load meshes and materials
-    let (models, materials) = load_obj(&path).expect("Couldn't open OBJ file");
-    let meshes = add_tangents_multi(&convert_meshes(&models));
-    let textures_path = path.parent().expect("Given path has no parent!");
-    println!("Searching for textures in {:?}", textures_path);
-    let texture_sets = load_textures(queue.clone(), textures_path, &materials);
+// i don't think the type annotation is necessary is here, but i included it
+// anyway to show how information about the object's uniforms is stored in its
+// type.
+type SceneObject = Object<(
+    // material and model matrix
+    Set<(Material, Matrix4)>,
+    // textures (diffuse, specular, normal)
+    Set<(Image, Image, Image)>,
+    // camera matrices, lights and shadow cascades
+    Set<(CameraData, LightListData, CascadeParams)>,
+)>;
+
+// model matrix and the cascade's view-projection matrix
+type CasterObject = Object<(Set<(Matrix4, Matrix4)>,)>;
+
+fn load_objects(
+    queue: Queue,
+    render_pass: RenderPass,
+    path: &Path,
+    camera_data: &CameraData,
+    light_data: &LightListData,
+    cascade_params: &CascadeParams,
+) -> Result<(Vec<SceneObject>, Mesh<VPos>), LoadError> {
+    // also returns all meshes merged into one with only positions, for
+    // casting shadows
+    let (models, materials) = load_obj(path)?;
+    let meshes = add_tangents_multi(&convert_meshes_with(&models, &ConvertOptions::default())?);
+    let caster_mesh = only_pos_from_ptnt(&merge(&meshes));
+    let textures_path = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut report = LoadReport::default();
+    report.add_models(&models, materials.len());
+    let texture_sets = load_textures_reported(queue.clone(), textures_path, &materials, &mut report)?;
+    print!("{}", report);
 
     let default_material = Material {
         ambient: [1.0, 1.0, 1.0, 0.0],
@@ -75,36 +275,17 @@ fn main() {
     let model_mat: Matrix4 = Mat4::identity().into();
 
     // combine the meshes and textures to create a list of renderable objects
-
-    // i don't think the type annotation is necessary is here, but i included it
-    // anyway to show how information about the object's uniforms is stored in
-    // its type.
-    let mut objects: Vec<
-        Object<(
-            // material and model matrix
-            Set<(Material, Matrix4)>,
-            // textures (diffuse, specular, normal)
-            Set<(Image, Image, Image)>,
-            // camera matrices and light position
-            Set<(CameraData, Light)>,
-        )>,
-    > = meshes
+    let objects: Vec<SceneObject> = meshes
         .into_iter()
         .enumerate()
         .map(|(idx, mesh)| {
-            let model = &models[idx];
+            let model_report = &report.models[idx];
+            let mat_idx = model_report.material;
 
-            let mat_idx = if let Some(idx) = model.mesh.material_id {
-                idx
+            let material = if model_report.material_fallback {
+                default_material.clone()
             } else {
-                println!("Model {} has no material id! Using 0.", model.name);
-                0
-            };
-
-            let material = if model.mesh.material_id.is_some() && mat_idx < materials.len() {
                 Material::from_tobj(&materials[mat_idx])
-            } else {
-                default_material.clone()
             };
 
             let textures = texture_sets[mat_idx].clone();
@@ -119,7 +300,7 @@ fn main() {
                 collection: (
                     (material.clone(), model_mat),
                     textures,
-                    (camera.get_data(), light_data.clone()),
+                    (camera_data.clone(), *light_data, *cascade_params),
                 ),
                 custom_dynamic_state: None,
             }
@@ -129,61 +310,7 @@ fn main() {
         })
         .collect();
 
-    println!("Objects Loaded: {}", objects.len());
-
-    // used in main loop
-    while !window.update() {
-        // get updated info on camera and light
-        camera.update(window.get_frame_info());
-        let camera_data = camera.get_data();
-        let light_data = moving_light.get_data();
-
-        // update collections
-        objects.iter_mut().for_each(|obj| {
-            obj.collection.2.data.0 = camera_data.clone();
-            obj.collection.2.data.1 = light_data.clone();
-            obj.collection.2.upload(device.clone());
-        });
-
-        // draw
-        system.start_window(&mut window);
-
-        for object in objects.iter() {
-            system.add_object(object);
-        }
-
-        system.finish_to_window(&mut window);
-    }
-
-    println!("FPS: {}", window.get_fps());
-}
-
-#[derive(Clone)]
-struct Light {
-    direction: [f32; 4],
-    power: f32,
-}
-
-impl Data for Light {}
-
-struct MovingLight {
-    start_time: std::time::Instant,
-}
-
-impl MovingLight {
-    fn new() -> Self {
-        Self {
-            start_time: std::time::Instant::now(),
-        }
-    }
-
-    fn get_data(&self) -> Light {
-        let time = get_elapsed(self.start_time) / 4.0;
-        Light {
-            direction: [time.sin(), 2.0, time.cos(), 0.0],
-            power: 1.0,
-        }
-    }
+    Ok((objects, caster_mesh))
 }
 
 #[derive(Clone)]
@@ -1,101 +1,96 @@
 use render_engine::collection::Data;
-use render_engine::input::{get_elapsed, VirtualKeyCode};
+use render_engine::input::VirtualKeyCode;
 use render_engine::mesh::PrimitiveTopology;
 use render_engine::object::{Drawcall, Object, ObjectPrototype};
 use render_engine::render_passes;
-use render_engine::system::{Pass, System};
+use render_engine::system::System;
 use render_engine::utils::Timer;
 use render_engine::window::Window;
-use render_engine::{Format, Image};
-
-use vulkano::command_buffer::DynamicState;
-use vulkano::pipeline::viewport::Viewport;
 
 use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use nalgebra_glm::*;
 
+use tests_render_engine::camera_path::{CameraPath, PathCamera};
+use tests_render_engine::clusters::{ClusterGrid, MAX_CLUSTER_INDICES};
+use tests_render_engine::culling::{cull_objects, Aabb, Frustum};
+use tests_render_engine::error::LoadError;
+use tests_render_engine::input::{CameraInput, InputTracker};
+use tests_render_engine::light::{PointLight, MAX_LIGHTS};
+use tests_render_engine::light_animation::{
+    sample_lights, AnimatedLight, AnimationClock, IntensityCurve, Motion,
+};
 use tests_render_engine::mesh::{
-    add_tangents_multi, convert_meshes, fullscreen_quad, load_obj, load_textures, merge, only_pos,
-    only_pos_from_ptnt, wireframe,
+    add_tangents_multi, convert_meshes_with, fullscreen_quad, load_obj, load_textures_reported,
+    merge, only_pos, only_pos_from_ptnt, wireframe, ConvertOptions,
 };
-use tests_render_engine::{relative_path, FlyCamera, Matrix4};
-
-const SHADOW_MAP_DIMS: [u32; 2] = [6_144, 1024];
-const PATCH_DIMS: [f32; 2] = [1024.0, 1024.0];
+use tests_render_engine::recording::{Playback, Recording};
+use tests_render_engine::render_graph::RenderGraph;
+use tests_render_engine::report::LoadReport;
+use tests_render_engine::shadow::{
+    CasterState, ShadowCache, ShadowCubemap, ShadowFilter, ShadowFiltering,
+};
+use tests_render_engine::{relative_path, Camera, FlyCamera, Matrix4};
 
 fn main() {
+    // --record <path> saves the camera input of this run, --play <path> replays
+    // one instead of reading the mouse and keyboard and quits when it's over.
+    // --path <path> picks the camera path P starts.
+    let args: Vec<String> = env::args().collect();
+    let camera_path_file =
+        arg_value(&args, "--path").unwrap_or_else(|| relative_path("paths/sponza.ron"));
+    let record_path = arg_value(&args, "--record");
+    let mut recording = record_path.as_ref().map(|_| Recording::new());
+    let mut playback = match arg_value(&args, "--play").map(|path| Recording::load(&path)) {
+        Some(Ok(recording)) => Some(Playback::new(recording)),
+        Some(Err(e)) => {
+            println!("Couldn't load recording: {}", e);
+            return;
+        }
+        None => None,
+    };
+
     // initialize window
     let (mut window, queue) = Window::new();
     let device = queue.device().clone();
 
-    // create system
-    let patched_shadow: Image = vulkano::image::AttachmentImage::sampled(
-        device.clone(),
-        SHADOW_MAP_DIMS,
-        Format::D32Sfloat,
-    )
-    .unwrap();
-    let shadow_blur: Image = vulkano::image::AttachmentImage::sampled(
-        device.clone(),
-        // SHADOW_MAP_DIMS,
-        SHADOW_MAP_DIMS,
-        Format::D32Sfloat,
-    )
-    .unwrap();
-    let mut custom_images = HashMap::new();
-    custom_images.insert("shadow_map", patched_shadow);
-    custom_images.insert("shadow_map_blur", shadow_blur);
+    // create system from one of the graphs in graphs/pretty/. the shadow
+    // passes depend on how shadows are filtered, so it's rebuilt whenever F
+    // switches to another filter, which also picks up any edits to the graph
+    // files. there's also a version without the shadow passes for frames where
+    // nothing the shadow map shows has changed. both render to the same shadow
+    // images, so that one keeps using whatever the other rendered last.
+    let shadow_cubemap = ShadowCubemap::default();
+    // far, bias and the cubemap's layout, for casters and receivers alike
+    let shadow_params = shadow_cubemap.shader_params();
+    let mut shadow_filtering = ShadowFiltering::default();
 
     let render_pass = render_passes::read_depth(device.clone());
-    let rpass_shadow = render_passes::only_depth(device.clone());
-    let rpass_shadow_blur = render_passes::only_depth(device.clone());
-    let rpass_cubeview = render_passes::basic(device.clone());
-    let rpass_prepass = render_passes::only_depth(device.clone());
-
-    let mut system = System::new(
-        queue.clone(),
-        vec![
-            // renders to shadow cubemap
-            Pass {
-                name: "shadow",
-                images_created_tags: vec!["shadow_map"],
-                images_needed_tags: vec![],
-                render_pass: rpass_shadow.clone(),
-            },
-            // blurs shadow cubemap
-            Pass {
-                name: "shadow_blur",
-                images_created_tags: vec!["shadow_map_blur"],
-                images_needed_tags: vec!["shadow_map"],
-                render_pass: rpass_shadow_blur.clone(),
-            },
-            // depth prepass
-            Pass {
-                name: "depth_prepass",
-                images_created_tags: vec!["depth_prepass"],
-                images_needed_tags: vec![],
-                render_pass: rpass_prepass.clone(),
-            },
-            // displays any depth buffer for debugging
-            Pass {
-                name: "depth_viewer",
-                images_created_tags: vec!["depth_view"],
-                images_needed_tags: vec!["depth_prepass"],
-                render_pass: rpass_cubeview.clone(),
-            },
-            // final pass
-            Pass {
-                name: "geometry",
-                images_created_tags: vec!["color", "depth_prepass"],
-                images_needed_tags: vec!["shadow_map_blur"],
-                render_pass: render_pass.clone(),
-            },
-        ],
-        custom_images,
-        "color",
-    );
+    let mut image_sizes = HashMap::new();
+    image_sizes.insert("shadow_cubemap", shadow_cubemap.dimensions());
+
+    let create_systems = |filter: ShadowFilter| -> Result<(System, System), LoadError> {
+        let graph = RenderGraph::load(&graph_file(filter), &image_sizes)?;
+        let shadow_images = graph.create_images(device.clone())?;
+
+        Ok((
+            graph.build(queue.clone(), &shadow_images),
+            graph
+                .without_passes(&["shadow", "shadow_blur"])
+                .build(queue.clone(), &shadow_images),
+        ))
+    };
+    let (mut system, mut cached_system) = match create_systems(shadow_filtering.filter) {
+        Ok(systems) => systems,
+        Err(e) => {
+            println!("Couldn't load render graph: {}", e);
+            return;
+        }
+    };
+    let mut shadow_cache = ShadowCache::new();
 
     window.set_render_pass(render_pass.clone());
 
@@ -105,12 +100,13 @@ fn main() {
     camera.position = vec3(0.0, 10.0, 0.0);
     let camera_data = camera.get_data();
 
-    // light
-    let light = MovingLight::new();
-    let light_data = light.get_data();
+    // lights are animated on their own clock. L pauses it, [ and ] scrub
+    // through it.
+    let mut light_clock = AnimationClock::new();
 
     // a model buffer with .1 scale, used for a couple different objects
-    let model_data: Matrix4 = scale(&Mat4::identity(), &vec3(0.1, 0.1, 0.1)).into();
+    let model_mat = scale(&Mat4::identity(), &vec3(0.1, 0.1, 0.1));
+    let model_data: Matrix4 = model_mat.into();
 
     // a default material, at some point I want to get rid of Material
     // altogether and just use textures
@@ -123,26 +119,61 @@ fn main() {
     };
 
     // load obj
-    let (models, materials) =
-        load_obj(&relative_path("meshes/sponza/sponza.obj")).expect("Couldn't load OBJ file");
+    let (models, materials) = match load_obj(&relative_path("meshes/sponza/sponza.obj")) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("Couldn't load Sponza: {}", e);
+            return;
+        }
+    };
+
+    // the moving light is joined by one for every torch
+    let lights = scene_lights(&torch_lights(&models, &materials, &model_mat));
+    let light_list = sample_lights(&lights, light_clock.time);
+    let light_data = light_list.get_data();
+
+    // with that many lights, fragments only shade the ones that can reach
+    // them. which ones those are is worked out on the CPU every frame.
+    let cluster_grid = ClusterGrid::default();
+    let mut warned_overflow = false;
+    let (cluster_data, cluster_indices) = cluster_grid.assign(&camera_data, &light_list).get_data();
 
     // convert to meshes and load textures
-    let meshes = add_tangents_multi(&convert_meshes(&models));
-    let textures = load_textures(queue.clone(), &relative_path("meshes/sponza/"), &materials);
+    let meshes = match convert_meshes_with(&models, &ConvertOptions::default()) {
+        Ok(meshes) => add_tangents_multi(&meshes),
+        Err(e) => {
+            println!("Couldn't load Sponza: {}", e);
+            return;
+        }
+    };
+    let mut report = LoadReport::default();
+    report.add_models(&models, materials.len());
+    let textures = match load_textures_reported(
+        queue.clone(),
+        &relative_path("meshes/sponza/"),
+        &materials,
+        &mut report,
+    ) {
+        Ok(textures) => textures,
+        Err(e) => {
+            println!("Couldn't load Sponza's textures: {}", e);
+            return;
+        }
+    };
+
+    // pass --json to get something a script can read
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report);
+    }
 
     // create objects for the geometry pass
     let mut geo_objects: Vec<Object<_>> = meshes
         .iter()
         .enumerate()
         .map(|(idx, mesh)| {
-            let model = &models[idx];
-
-            let mat_idx = if let Some(idx) = model.mesh.material_id {
-                idx
-            } else {
-                println!("Model {} has no material id! Using 0.", model.name);
-                0
-            };
+            let mat_idx = report.models[idx].material;
             let textures = textures[mat_idx].clone();
 
             ObjectPrototype {
@@ -155,7 +186,14 @@ fn main() {
                 collection: (
                     (material_data.clone(), model_data),
                     textures,
-                    (camera_data.clone(), light_data.clone()),
+                    (
+                        camera_data.clone(),
+                        light_data.clone(),
+                        shadow_filtering.shader_params(),
+                        cluster_data,
+                        cluster_indices,
+                    ),
+                    (shadow_params,),
                 ),
                 custom_dynamic_state: None,
             }
@@ -165,6 +203,18 @@ fn main() {
 
     println!("Objects Loaded: {}", geo_objects.len());
 
+    // world space bounds for every geometry object, for frustum culling
+    let geo_bounds: Vec<Aabb> = meshes
+        .iter()
+        .map(|mesh| {
+            Aabb::from_positions(mesh.vertices.iter().map(|v| v.position))
+                .transform(&model_mat)
+        })
+        .collect();
+    let mut total_culled = 0;
+    let mut frame_count = 0;
+    let mut last_culled = None;
+
     // shadow stuff
     // create fullscreen quad to debug cubemap
     let quad_display = fullscreen_quad(
@@ -173,30 +223,43 @@ fn main() {
         relative_path("shaders/pretty/display_cubemap_frag.glsl"),
     );
 
-    // and to blur shadow map
-    let mut quad_blur = fullscreen_quad(
+    // and to blur VSM and ESM shadow maps
+    let quad_blur: Arc<dyn Drawcall> = Arc::new(fullscreen_quad(
         queue.clone(),
         relative_path("shaders/pretty/fullscreen_vert.glsl"),
         relative_path("shaders/pretty/blur_frag.glsl"),
-    );
-    quad_blur.pipeline_spec.write_depth = true;
+    ));
 
-    // merge meshes for use in depth prepass and shadow casting
+    // merge meshes for use in the depth prepass
     let merged_mesh = merge(&meshes);
     let merged_mesh_pos_only = only_pos_from_ptnt(&merged_mesh);
 
-    let shadow_cast_base = ObjectPrototype {
-        vs_path: relative_path("shaders/pretty/shadow_cast_vert.glsl"),
-        fs_path: relative_path("shaders/pretty/shadow_cast_frag.glsl"),
-        fill_type: PrimitiveTopology::TriangleList,
-        read_depth: true,
-        write_depth: true,
-        mesh: merged_mesh_pos_only.clone(),
-        // convert_to_shadow_casters adds proper collections
-        collection: (),
-        custom_dynamic_state: None,
-    }
-    .build(queue.clone());
+    // shadow casters stay separate so that each cubemap face only draws the
+    // ones it can see. ShadowCubemap::culled_casters adds proper collections.
+    let caster_bases = |fs_path: &str| -> Vec<Object<()>> {
+        meshes
+            .iter()
+            .map(|mesh| {
+                ObjectPrototype {
+                    vs_path: relative_path("shaders/pretty/shadow_cast_vert.glsl"),
+                    fs_path: relative_path(fs_path),
+                    fill_type: PrimitiveTopology::TriangleList,
+                    read_depth: true,
+                    write_depth: true,
+                    mesh: only_pos_from_ptnt(mesh),
+                    collection: (),
+                    custom_dynamic_state: None,
+                }
+                .build(queue.clone())
+            })
+            .collect()
+    };
+    let shadow_cast_bases = caster_bases("shaders/pretty/shadow_cast_frag.glsl");
+    // the same, but writes what VSM and ESM need instead of just depth
+    let shadow_cast_moments_bases = caster_bases("shaders/pretty/shadow_cast_moments_frag.glsl");
+    // Sponza doesn't move, so the shadow map only changes with the light
+    let caster_states: Vec<CasterState> =
+        geo_bounds.iter().cloned().map(CasterState::new).collect();
 
     let mut depth_prepass_object = ObjectPrototype {
         vs_path: relative_path("shaders/pretty/depth_prepass_vert.glsl"),
@@ -212,10 +275,14 @@ fn main() {
 
     // create mesh for light (just a sphere)
     // we need 2 objects: one for the depth prepass and one for the geometry stage
-    let light_mesh = {
-        let (models, _materials) =
-            load_obj(&relative_path("meshes/sphere.obj")).expect("Couldn't load OBJ file");
-        convert_meshes(&[models[0].clone()]).remove(0)
+    let light_mesh = match load_obj(&relative_path("meshes/sphere.obj"))
+        .and_then(|(models, _materials)| convert_meshes_with(&models, &ConvertOptions::default()))
+    {
+        Ok(mut meshes) => meshes.remove(0),
+        Err(e) => {
+            println!("Couldn't load light mesh: {}", e);
+            return;
+        }
     };
 
     let mut light_object_prepass = ObjectPrototype {
@@ -242,7 +309,14 @@ fn main() {
             // take the textures of the first object just to fill the space
             // maybe eventually give the light its own vertex shader
             textures[0].clone(),
-            (camera_data.clone(), light_data.clone()),
+            (
+                camera_data.clone(),
+                light_data.clone(),
+                shadow_filtering.shader_params(),
+                cluster_data,
+                cluster_indices,
+            ),
+            (shadow_params,),
         ),
         custom_dynamic_state: None,
     }
@@ -272,26 +346,168 @@ fn main() {
     let mut timer_draw = Timer::new("Overall draw time");
 
     all_objects.insert("depth_viewer", vec![Arc::new(quad_display)]);
-    all_objects.insert("shadow_blur", vec![Arc::new(quad_blur)]);
 
     let mut view_mode: i32 = 0;
     let mut update_view = false;
     let mut draw_wireframe = false;
     let mut cursor_grabbed = true;
+    let mut input_tracker = InputTracker::new();
+    let mut path_camera: Option<PathCamera> = None;
 
     while !window.update() {
         timer_setup.start();
 
-        // convert merged mesh into 6 casters, one for each cubemap face
-        let shadow_casters = convert_to_shadow_casters(shadow_cast_base.clone(), light.get_data());
-        // update camera, but only if we're grabbing the cursor
-        if cursor_grabbed {
-            camera.update(window.get_frame_info());
+        // update camera, from the recording if we're playing one back and from
+        // the window otherwise. if we aren't grabbing the cursor the camera
+        // still gets an input, just an empty one, so that recordings stay in
+        // sync with the frames.
+        let input = if let Some(playback) = playback.as_mut() {
+            match playback.next_input() {
+                Some(input) => input,
+                None => break,
+            }
+        } else {
+            let input = input_tracker.process(&window.get_frame_info());
+            if cursor_grabbed {
+                input
+            } else {
+                CameraInput {
+                    dimensions: input.dimensions,
+                    delta: input.delta,
+                    ..CameraInput::default()
+                }
+            }
+        };
+        if let Some(recording) = recording.as_mut() {
+            recording.push(&input);
+        }
+        // recordings only hold camera input, so hotkeys that change what's
+        // rendered are ignored while playing one back. otherwise pressing one
+        // would make the run differ from the recorded one.
+        let keydowns: Vec<VirtualKeyCode> = if playback.is_some() {
+            vec![]
+        } else {
+            window.get_frame_info().keydowns.iter().copied().collect()
+        };
+        // P starts the camera path, or stops it if it's already running
+        if keydowns.contains(&VirtualKeyCode::P) {
+            path_camera = match path_camera {
+                Some(_) => None,
+                None => match CameraPath::load(&camera_path_file) {
+                    Ok(path) => Some(PathCamera::new(path).with_projection(camera.projection)),
+                    Err(e) => {
+                        println!("Couldn't load camera path: {}", e);
+                        None
+                    }
+                },
+            };
         }
-        let camera_data = camera.get_data();
 
-        // update light
-        let light_data = light.get_data();
+        let camera_data = if let Some(path_cam) = path_camera.as_mut() {
+            path_cam.apply_input(&input);
+            path_cam.get_data()
+        } else {
+            camera.apply_input(&input);
+            camera.get_data()
+        };
+        if path_camera.as_ref().map(|cam| cam.is_finished()) == Some(true) {
+            path_camera = None;
+        }
+
+        // F switches to the next shadow filter. VSM and ESM need different
+        // passes, so the system has to be rebuilt.
+        if keydowns.contains(&VirtualKeyCode::F) {
+            let filter = shadow_filtering.filter.next();
+            match create_systems(filter) {
+                Ok((new_system, new_cached_system)) => {
+                    shadow_filtering.filter = filter;
+                    println!("Shadow filter: {}", filter.name());
+
+                    let output_tag = system.output_tag;
+                    system = new_system;
+                    cached_system = new_cached_system;
+                    system.output_tag = output_tag;
+                    // the new images are empty
+                    shadow_cache.invalidate();
+                }
+                // keep the old filter, the shaders have to match the passes
+                Err(e) => println!("Couldn't load render graph: {}", e),
+            }
+        }
+
+        // update lights. the clock runs on the input's deltas, so playing a
+        // recording back moves the lights exactly the same way.
+        if playback.is_none() {
+            light_clock.handle_keys(&window.get_frame_info());
+        }
+        light_clock.advance(input.delta);
+        let light_list = sample_lights(&lights, light_clock.time);
+        let light_data = light_list.get_data();
+        let assignment = cluster_grid.assign(&camera_data, &light_list);
+        if assignment.overflowed && !warned_overflow {
+            // only once, it'll usually keep happening for a while
+            println!(
+                "More than {} cluster light indices, some clusters are missing lights",
+                MAX_CLUSTER_INDICES
+            );
+            warned_overflow = true;
+        }
+        let (cluster_data, cluster_indices) = assignment.get_data();
+        let bulb_position = light_list
+            .lights()
+            .next()
+            .and_then(|light| light.position())
+            .unwrap_or_else(|| vec3(0.0, 0.0, 0.0));
+
+        // render the shadow map again only if the light moved, and then only
+        // the casters each face can see
+        let caster_light = Light {
+            position: [bulb_position.x, bulb_position.y, bulb_position.z, 0.0],
+            strength: 1.0,
+        };
+        let filter_params = shadow_filtering.shader_params();
+        let update_shadows =
+            shadow_cache.needs_update(bulb_position, shadow_cubemap.far, &caster_states);
+        let shadow_casters: Vec<Arc<dyn Drawcall>> = if !update_shadows {
+            vec![]
+        } else if shadow_filtering.filter.uses_moments() {
+            shadow_cubemap
+                .culled_casters(
+                    &shadow_cast_moments_bases,
+                    &geo_bounds,
+                    bulb_position,
+                    |face| {
+                        (
+                            (model_data,),
+                            (Matrix4::from(face.proj),),
+                            (Matrix4::from(face.view),),
+                            (caster_light.clone(), filter_params, shadow_params),
+                        )
+                    },
+                )
+                .into_iter()
+                .map(|obj| {
+                    let dc: Arc<dyn Drawcall> = Arc::new(obj);
+                    dc
+                })
+                .collect()
+        } else {
+            shadow_cubemap
+                .culled_casters(&shadow_cast_bases, &geo_bounds, bulb_position, |face| {
+                    (
+                        (model_data,),
+                        (Matrix4::from(face.proj),),
+                        (Matrix4::from(face.view),),
+                        (caster_light.clone(), shadow_params),
+                    )
+                })
+                .into_iter()
+                .map(|obj| {
+                    let dc: Arc<dyn Drawcall> = Arc::new(obj);
+                    dc
+                })
+                .collect()
+        };
 
         // update depth prepass objects' collections
         (depth_prepass_object.collection.1).0 = camera_data.clone();
@@ -299,7 +515,7 @@ fn main() {
 
         // the light has moved, we need to update its model matrix
         let light_model_data: Matrix4 = scale(
-            &translate(&Mat4::identity(), &make_vec3(&light_data.position)),
+            &translate(&Mat4::identity(), &bulb_position),
             &vec3(0.03, 0.03, 0.03),
         )
         .into();
@@ -313,12 +529,7 @@ fn main() {
             ],
         );
 
-        if window
-            .get_frame_info()
-            .keydowns
-            .contains(&VirtualKeyCode::C)
-            || update_view
-        {
+        if keydowns.contains(&VirtualKeyCode::C) || update_view {
             view_mode += 1;
             update_view = false;
 
@@ -425,6 +636,14 @@ fn main() {
                     });
                     system.output_tag = "color";
                 }
+                14 => {
+                    // how many lights each cluster shades
+                    geo_objects.iter_mut().for_each(|obj| {
+                        obj.pipeline_spec.fs_path =
+                            relative_path("shaders/pretty/cluster_count_frag.glsl");
+                    });
+                    system.output_tag = "color";
+                }
                 _ => {
                     geo_objects.iter_mut().for_each(|obj| {
                         obj.pipeline_spec.fs_path = relative_path("shaders/pretty/all_frag.glsl");
@@ -435,11 +654,7 @@ fn main() {
             }
         }
 
-        if window
-            .get_frame_info()
-            .keydowns
-            .contains(&VirtualKeyCode::V)
-        {
+        if keydowns.contains(&VirtualKeyCode::V) {
             view_mode -= 2;
             update_view = true;
         }
@@ -461,43 +676,61 @@ fn main() {
 
         (light_object_geo.collection.0).1 = light_model_data;
 
-        geo_objects
-            .iter_mut()
-            .for_each(|obj| obj.collection.2 = (camera_data.clone(), light_data.clone()));
+        geo_objects.iter_mut().for_each(|obj| {
+            obj.collection.2 = (
+                camera_data.clone(),
+                light_data.clone(),
+                filter_params,
+                cluster_data,
+                cluster_indices,
+            )
+        });
 
         if draw_wireframe {
             (wireframe_object.collection.1).0 = camera_data.clone();
             // geometry_object_list.push(cur_wireframe_object.clone());
         }
 
-        if window
-            .get_frame_info()
-            .keydowns
-            .contains(&VirtualKeyCode::R)
-        {
+        if keydowns.contains(&VirtualKeyCode::R) {
             draw_wireframe = !draw_wireframe;
         }
 
+        // only draw what the camera can see
+        let frustum = Frustum::from_camera_data(&camera_data);
+        let culled = cull_objects(&frustum, &geo_objects, &geo_bounds);
+        total_culled += culled.culled;
+        frame_count += 1;
+        // this frame's count goes in the title, only touching it when the
+        // count changes
+        if last_culled != Some(culled.culled) {
+            last_culled = Some(culled.culled);
+            window.get_surface().window().set_title(&format!(
+                "pretty: {} of {} objects culled",
+                culled.culled,
+                geo_objects.len()
+            ));
+        }
+
         all_objects.insert(
             "geometry",
-            geo_objects
-                .iter()
-                .map(|obj| {
-                    let dc: Arc<dyn Drawcall> = Arc::new(obj.clone());
-                    dc
-                })
-                .collect(),
-        );
-        all_objects.insert(
-            "shadow",
-            shadow_casters
-                .iter()
+            culled
+                .visible
+                .into_iter()
                 .map(|obj| {
                     let dc: Arc<dyn Drawcall> = Arc::new(obj.clone());
                     dc
                 })
                 .collect(),
         );
+        // the cached system doesn't have the shadow passes at all
+        all_objects.remove("shadow");
+        all_objects.remove("shadow_blur");
+        if update_shadows {
+            all_objects.insert("shadow", shadow_casters);
+            if shadow_filtering.filter.uses_moments() {
+                all_objects.insert("shadow_blur", vec![quad_blur.clone()]);
+            }
+        }
 
         /*
         let mut cur_wireframe_object = wireframe_object.clone();
@@ -508,7 +741,12 @@ fn main() {
 
         // draw
         timer_draw.start();
-        system.render_to_window(&mut window, all_objects.clone());
+        if update_shadows {
+            system.render_to_window(&mut window, all_objects.clone());
+        } else {
+            cached_system.output_tag = system.output_tag;
+            cached_system.render_to_window(&mut window, all_objects.clone());
+        }
         timer_draw.stop();
     }
 
@@ -517,129 +755,131 @@ fn main() {
     println!("Avg. delta: {} ms", window.get_avg_delta() * 1_000.0);
     timer_setup.print();
     timer_draw.print();
-}
+    println!(
+        "Avg. objects culled: {:.1} of {}",
+        total_culled as f32 / frame_count.max(1) as f32,
+        geo_objects.len()
+    );
 
-#[allow(dead_code)]
-#[derive(Clone)]
-struct Light {
-    position: [f32; 4],
-    strength: f32,
-}
+    if let Some(playback) = playback {
+        println!(
+            "Played back {} of {} frames",
+            playback.frames_played(),
+            playback.frame_count()
+        );
+    }
 
-impl Data for Light {}
+    if let (Some(recording), Some(path)) = (recording, record_path) {
+        match recording.save(&path) {
+            Ok(()) => println!(
+                "Recorded {} frames ({:.1} s) to {:?}",
+                recording.frames.len(),
+                recording.duration(),
+                path
+            ),
+            Err(e) => println!("Couldn't save recording to {:?}: {}", path, e),
+        }
+    }
+}
 
-struct MovingLight {
-    start_time: std::time::Instant,
+fn graph_file(filter: ShadowFilter) -> PathBuf {
+    // VSM and ESM blur their shadow maps, each in its own format
+    relative_path(match filter {
+        ShadowFilter::Vsm => "graphs/pretty/vsm.ron",
+        ShadowFilter::Esm => "graphs/pretty/esm.ron",
+        _ => "graphs/pretty/depth.ron",
+    })
 }
 
-impl MovingLight {
-    fn new() -> Self {
-        Self {
-            start_time: std::time::Instant::now(),
-        }
-    }
+fn arg_value(args: &[String], flag: &str) -> Option<PathBuf> {
+    // the argument after flag, if there is one
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+        .map(PathBuf::from)
+}
 
-    fn get_data(&self) -> Light {
-        let time = get_elapsed(self.start_time) / 16.0;
-        Light {
-            position: [time.sin() * 100.0, 10.0, 0.0, 0.0],
-            strength: 1.0,
-        }
-    }
+fn scene_lights(torches: &[PointLight]) -> Vec<AnimatedLight> {
+    // the moving light comes first and is the only one with a shadow map, the
+    // debug shaders only look at the first light
+    let bulb = AnimatedLight::new(
+        PointLight::new(vec3(0.0, 10.0, 0.0))
+            .with_range(250.0)
+            .with_color(vec3(1.0, 1.0, 1.0), 2000.0),
+    )
+    .with_motion(Motion::linear(
+        vec3(-100.0, 10.0, 0.0),
+        vec3(100.0, 10.0, 0.0),
+        16.0 * std::f32::consts::PI,
+    ))
+    .with_shadow(0);
+
+    // every torch flickers a little differently
+    std::iter::once(bulb)
+        .chain(torches.iter().enumerate().map(|(idx, torch)| {
+            AnimatedLight::new(*torch).with_intensity(IntensityCurve::flicker(0.3, 6.0, idx as u32))
+        }))
+        .collect()
 }
 
-fn convert_to_shadow_casters(
-    base_object: Object<()>,
-    light_data: Light,
-) -> Vec<Object<((Matrix4,), (Matrix4,), (Matrix4,), (Light,))>> {
-    // if you want to make point lamps cast shadows, you need shadow cubemaps
-    // render-engine doesn't support geometry shaders, so the easiest way to do
-    // this is to convert one object into 6 different ones, one for each face of
-    // the cubemap, that each render to a different part of a 2D texture.
-    // for now this function assumes a 6x1 patch layout
-    let view_directions = [
-        vec3(1.0, 0.0, 0.0),
-        vec3(-1.0, 0.0, 0.0),
-        vec3(0.0, 1.0, 0.0),
-        vec3(0.0, -1.0, 0.0),
-        vec3(0.0, 0.0, 1.0),
-        vec3(0.0, 0.0, -1.0),
-    ];
-
-    let up_directions = [
-        vec3(0.0, -1.0, 0.0),
-        vec3(0.0, -1.0, 0.0),
-        vec3(0.0, 0.0, 1.0),
-        vec3(0.0, 0.0, -1.0),
-        vec3(0.0, -1.0, 0.0),
-        vec3(0.0, -1.0, 0.0),
-    ];
-
-    let patch_positions = [
-        [0.0, 0.0],
-        [1.0, 0.0],
-        [2.0, 0.0],
-        [3.0, 0.0],
-        [4.0, 0.0],
-        [5.0, 0.0],
-    ];
-
-    let (near, far) = (1.0, 250.0);
-    // pi / 2 = 90 deg., 1.0 = aspect ratio
-    // we use a fov 1% too big to make sure sampling doesn't go between patches
-    let proj_data: Matrix4 = perspective(1.0, std::f32::consts::PI / 2.0 * 1.01, near, far).into();
-
-    let model_data: Matrix4 = scale(&Mat4::identity(), &vec3(0.1, 0.1, 0.1)).into();
-
-    let light_pos = make_vec3(&light_data.position);
-
-    view_directions
+fn torch_lights(
+    models: &[tobj::Model],
+    materials: &[tobj::Material],
+    model_mat: &Mat4,
+) -> Vec<PointLight> {
+    // sponza's torches are the hanging vases, but they're all part of one
+    // model. group their vertices into blobs and put a light in each one.
+    let radius = 10.0;
+    let mut clusters: Vec<(Vec3, f32)> = vec![];
+
+    models
         .iter()
-        .zip(&up_directions)
-        .zip(&patch_positions)
-        .map(|((dir, up), patch_pos): ((&Vec3, &Vec3), &[f32; 2])| {
-            let view_data: Matrix4 = look_at(&light_pos, &(light_pos + dir), up).into();
-
-            // dynamic state for the current cubemap face, represents which part
-            // of the patched texture we draw to
-            let margin = 0.0;
-            let origin = [
-                patch_pos[0] * PATCH_DIMS[0] + margin,
-                patch_pos[1] * PATCH_DIMS[1] + margin,
-            ];
-            let dynamic_state = dynamic_state_for_bounds(
-                origin,
-                [PATCH_DIMS[0] - margin * 2.0, PATCH_DIMS[1] - margin * 2.0],
-            );
-
-            Object {
-                pipeline_spec: base_object.pipeline_spec.clone(),
-                vbuf: base_object.vbuf.clone(),
-                ibuf: base_object.ibuf.clone(),
-                collection: (
-                    (model_data,),
-                    (proj_data,),
-                    (view_data,),
-                    (light_data.clone(),),
-                ),
-                custom_dynamic_state: Some(dynamic_state),
+        .filter(|model| {
+            model
+                .mesh
+                .material_id
+                .and_then(|id| materials.get(id))
+                .map(|material| material.name == "vase_hanging")
+                == Some(true)
+        })
+        .flat_map(|model| model.mesh.positions.chunks(3))
+        .map(|pos| (model_mat * vec4(pos[0], pos[1], pos[2], 1.0)).xyz())
+        .for_each(|pos| {
+            match clusters
+                .iter_mut()
+                .find(|(center, _)| distance(center, &pos) < radius)
+            {
+                // keep a running average so the light ends up in the middle
+                Some((center, count)) => {
+                    *count += 1.0;
+                    *center += (pos - *center) / *count;
+                }
+                None => clusters.push((pos, 1.0)),
             }
+        });
+
+    // the flame sits a bit above the vase's middle, and the first slot is
+    // taken by the moving light
+    clusters
+        .iter()
+        .take(MAX_LIGHTS - 1)
+        .map(|(center, _)| {
+            PointLight::new(center + vec3(0.0, 1.0, 0.0))
+                .with_range(60.0)
+                .with_color(vec3(1.0, 0.6, 0.3), 300.0)
         })
         .collect()
 }
 
-fn dynamic_state_for_bounds(origin: [f32; 2], dimensions: [f32; 2]) -> DynamicState {
-    DynamicState {
-        line_width: None,
-        viewports: Some(vec![Viewport {
-            origin,
-            dimensions,
-            depth_range: 0.0..1.0,
-        }]),
-        scissors: None,
-    }
+#[allow(dead_code)]
+#[derive(Clone)]
+struct Light {
+    position: [f32; 4],
+    strength: f32,
 }
 
+impl Data for Light {}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 struct Material {
@@ -0,0 +1,256 @@
+use render_engine::mesh::PrimitiveTopology;
+use render_engine::object::{Drawcall, ObjectPrototype};
+use render_engine::render_passes;
+use render_engine::system::{Pass, System};
+use render_engine::window::Window;
+use render_engine::{Format, Image};
+
+use nalgebra_glm::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tests_render_engine::light::{Light, LightList, SpotLight};
+use tests_render_engine::light_animation::{sample_lights, AnimatedLight, AnimationClock, Motion};
+use tests_render_engine::mesh::{convert_meshes, load_obj, merge, only_pos};
+use tests_render_engine::projection::Projection;
+use tests_render_engine::shadow::spot::MAX_SPOT_SHADOWS;
+use tests_render_engine::shadow::{
+    screen_coverage, ShadowAtlas, SpotShadow, SpotShadowMaps, TileRequest,
+};
+use tests_render_engine::{relative_path, Camera, FlyCamera, Matrix4};
+
+const LIGHT_COUNT: usize = 3;
+
+fn main() {
+    // initialize window
+    let (mut window, queue) = Window::new();
+    let device = queue.device().clone();
+
+    // create system. every light's shadow map goes somewhere in the atlas,
+    // bigger the more of the screen the light reaches.
+    let spot_shadows = SpotShadowMaps::new(LIGHT_COUNT);
+    let shadow_atlas = ShadowAtlas::new(2048).with_tile_range(256, 1024);
+    let shadow_image: Image = vulkano::image::AttachmentImage::sampled(
+        device.clone(),
+        shadow_atlas.dimensions(),
+        Format::D32Sfloat,
+    )
+    .unwrap();
+    let mut custom_images = HashMap::new();
+    custom_images.insert("spot_shadows", shadow_image);
+
+    let rpass_shadow = render_passes::only_depth(device.clone());
+    let rpass_final = render_passes::with_depth(device.clone());
+
+    let mut system = System::new(
+        queue.clone(),
+        vec![
+            // renders every light's shadow map to its own patch
+            Pass {
+                name: "shadow",
+                images_created_tags: vec!["spot_shadows"],
+                images_needed_tags: vec![],
+                render_pass: rpass_shadow.clone(),
+            },
+            // renders final scene
+            Pass {
+                name: "final",
+                images_created_tags: vec!["final_color", "final_depth"],
+                images_needed_tags: vec!["spot_shadows"],
+                render_pass: rpass_final.clone(),
+            },
+        ],
+        custom_images,
+        "final_color",
+    );
+    window.set_render_pass(rpass_final.clone());
+
+    // initialize camera
+    let mut camera = FlyCamera::default()
+        .with_position(vec3(0.0, 10.0, 40.0))
+        .with_rotation(-0.2, -std::f32::consts::FRAC_PI_2)
+        .with_clip_planes(0.1, 1_000.0);
+
+    // load object
+    let (models, _materials) = match load_obj(&relative_path("meshes/shadowtest.obj")) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("Couldn't load the scene: {}", e);
+            return;
+        }
+    };
+    let mesh = match convert_meshes(&models) {
+        Ok(meshes) => merge(&meshes),
+        Err(e) => {
+            println!("Couldn't load the scene: {}", e);
+            return;
+        }
+    };
+    let model_data: Matrix4 = Mat4::identity().into();
+
+    // L pauses the lights, [ and ] scrub through their animation
+    let lights = swinging_lights();
+    let mut light_clock = AnimationClock::new();
+    let (light_list, shadows) = allocate_shadows(
+        &sample_lights(&lights, light_clock.time),
+        &spot_shadows,
+        &shadow_atlas,
+        &camera.position,
+        &camera.projection,
+    );
+
+    let caster_base = ObjectPrototype {
+        vs_path: relative_path("shaders/spot-shadow/shadow_cast_vert.glsl"),
+        fs_path: relative_path("shaders/spot-shadow/shadow_cast_frag.glsl"),
+        fill_type: PrimitiveTopology::TriangleList,
+        read_depth: true,
+        write_depth: true,
+        mesh: only_pos(&mesh),
+        // SpotShadowMaps::casters adds proper collections
+        collection: (),
+        custom_dynamic_state: None,
+    }
+    .build(queue.clone());
+
+    let mut scene = ObjectPrototype {
+        vs_path: relative_path("shaders/spot-shadow/vert.glsl"),
+        fs_path: relative_path("shaders/spot-shadow/frag.glsl"),
+        fill_type: PrimitiveTopology::TriangleList,
+        read_depth: true,
+        write_depth: true,
+        mesh,
+        collection: (
+            (model_data,),
+            (
+                camera.get_data(),
+                light_list.get_data(),
+                spot_shadows.shader_params(&shadows),
+            ),
+        ),
+        custom_dynamic_state: None,
+    }
+    .build(queue.clone());
+
+    while !window.update() {
+        camera.update(window.get_frame_info());
+
+        // move the lights, and with them their shadow maps
+        light_clock.update(&window.get_frame_info());
+        let (light_list, shadows) = allocate_shadows(
+            &sample_lights(&lights, light_clock.time),
+            &spot_shadows,
+            &shadow_atlas,
+            &camera.position,
+            &camera.projection,
+        );
+        let casters = spot_shadows.casters(&caster_base, &shadows, |shadow| {
+            ((model_data, Matrix4::from(shadow.view_projection())),)
+        });
+
+        scene.collection.1 = (
+            camera.get_data(),
+            light_list.get_data(),
+            spot_shadows.shader_params(&shadows),
+        );
+
+        let mut all_objects: HashMap<&str, Vec<Arc<dyn Drawcall>>> = HashMap::new();
+        all_objects.insert(
+            "shadow",
+            casters
+                .into_iter()
+                .map(|obj| {
+                    let dc: Arc<dyn Drawcall> = Arc::new(obj);
+                    dc
+                })
+                .collect(),
+        );
+        all_objects.insert("final", vec![Arc::new(scene.clone())]);
+
+        system.render_to_window(&mut window, all_objects);
+    }
+
+    println!("FPS: {}", window.get_fps());
+}
+
+fn swinging_lights() -> Vec<AnimatedLight> {
+    // a few coloured lights above the scene, each circling at its own pace and
+    // always aiming at the middle
+    let colors = [
+        vec3(1.0, 0.4, 0.3),
+        vec3(0.3, 1.0, 0.4),
+        vec3(0.4, 0.5, 1.0),
+    ];
+
+    colors
+        .iter()
+        .enumerate()
+        .map(|(idx, color)| {
+            let spot = SpotLight::new(vec3(15.0, 20.0, 0.0), vec3(0.0, -1.0, 0.0))
+                .with_cone(0.25, 0.45)
+                .with_range(80.0)
+                .with_color(*color, 400.0);
+
+            AnimatedLight::new(spot)
+                .with_motion(Motion::Orbit {
+                    center: vec3(0.0, 0.0, 0.0),
+                    radius: 15.0,
+                    height: 20.0,
+                    period: std::f32::consts::PI * 2.0 / (0.3 + idx as f32 * 0.1),
+                    phase: idx as f32 * std::f32::consts::PI * 2.0 / LIGHT_COUNT as f32,
+                })
+                .aiming_at(vec3(0.0, 0.0, 0.0))
+        })
+        .collect()
+}
+
+fn allocate_shadows(
+    lights: &LightList,
+    spot_shadows: &SpotShadowMaps,
+    atlas: &ShadowAtlas,
+    camera_position: &Vec3,
+    projection: &Projection,
+) -> (LightList, Vec<SpotShadow>) {
+    // asks the atlas for a tile per spot light, sized by how much of the
+    // screen its cone could light. lights that didn't get one go back in the
+    // list without a shadow map, the rest get the next free shadow slot.
+    let spots: Vec<SpotLight> = lights
+        .lights()
+        .filter_map(|light| match light {
+            Light::Spot(spot) => Some(*spot),
+            _ => None,
+        })
+        .collect();
+    // the shader only has room for so many, the rest go unshadowed
+    let requests: Vec<TileRequest> = spots
+        .iter()
+        .take(MAX_SPOT_SHADOWS)
+        .map(|spot| TileRequest {
+            tiles: 1,
+            resolution: atlas.resolution_for(
+                screen_coverage(camera_position, projection, &spot.position, spot.range),
+                1.0,
+            ),
+            priority: spot.intensity,
+        })
+        .collect();
+    let tiles = atlas.allocate(&requests);
+
+    let mut list = LightList::new();
+    let mut shadows = vec![];
+    for (idx, spot) in spots.iter().enumerate() {
+        match tiles
+            .get(idx)
+            .and_then(|tile| tile.as_ref())
+            .and_then(|tiles| tiles.first())
+        {
+            Some(tile) => {
+                list.push_shadowed(*spot, shadows.len());
+                shadows.push(spot_shadows.shadow_in_tile(spot, shadows.len(), tile, atlas));
+            }
+            None => list.push(*spot),
+        }
+    }
+
+    (list, shadows)
+}
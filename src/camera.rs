@@ -0,0 +1,643 @@
+use render_engine::collection::Data;
+use render_engine::input::FrameInfo;
+
+use nalgebra_glm::*;
+
+use crate::input::{CameraInput, InputTracker};
+use crate::projection::{DepthMode, Projection};
+
+// anything that can be moved around by the user and turned into a view and
+// projection matrix. binaries that only use these methods can switch cameras
+// freely.
+pub trait Camera {
+    // reads this frame's input from the window and applies it
+    fn update(&mut self, frame_info: FrameInfo);
+
+    fn apply_input(&mut self, input: &CameraInput);
+
+    fn view_matrix(&self) -> Mat4;
+
+    // maps view space to vulkan clip space, see projection.rs for what that
+    // means for the depth buffer
+    fn projection_matrix(&self) -> Mat4;
+
+    // where the eye is in world space
+    fn position(&self) -> Vec3;
+
+    fn get_data(&self) -> CameraData {
+        CameraData::new(self.view_matrix(), self.projection_matrix(), self.position())
+    }
+}
+
+#[derive(Clone)]
+pub struct OrbitCamera {
+    pub center_position: Vec3,
+    pub front: Vec3,
+    up: Vec3,
+    right: Vec3,
+    world_up: Vec3,
+    // pitch and yaw are in radians
+    pub pitch: f32,
+    pub yaw: f32,
+    pub orbit_distance: f32,
+    // scrolling can't zoom past these
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub mouse_sens: f32,
+    // panning moves the center by this much per pixel of mouse movement and
+    // unit of orbit distance, so it feels the same at every zoom level
+    pub pan_sens: f32,
+    // how quickly focus_on gets to its target, higher is faster
+    pub focus_speed: f32,
+    pub projection: Projection,
+    pub depth: DepthMode,
+    focus_target: Option<Vec3>,
+    input: InputTracker,
+    aspect_ratio: f32,
+    view_mat: Mat4,
+}
+
+impl OrbitCamera {
+    pub fn default() -> Self {
+        let pitch: f32 = 0.0;
+        let yaw: f32 = std::f32::consts::PI / 2.0;
+
+        Self {
+            center_position: vec3(0.0, 0.0, 0.0),
+            front: front_from_angles(pitch, yaw),
+            up: vec3(0.0, 1.0, 0.0),
+            right: vec3(0.0, 0.0, 0.0),
+            world_up: vec3(0.0, 1.0, 0.0),
+            pitch,
+            yaw,
+            orbit_distance: 20.0,
+            min_distance: 0.1,
+            max_distance: 1_000.0,
+            mouse_sens: 0.0007,
+            pan_sens: 0.001,
+            focus_speed: 8.0,
+            projection: Projection::default(),
+            depth: DepthMode::default(),
+            focus_target: None,
+            input: InputTracker::new(),
+            aspect_ratio: 1.0,
+            view_mat: Mat4::identity(),
+        }
+    }
+
+    // builders, every one of these is also a public field that can be changed
+    // between frames
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_depth_mode(mut self, depth: DepthMode) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        self.projection.set_fov(fov);
+        self
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.projection.set_clip_planes(near, far);
+        self
+    }
+
+    pub fn with_mouse_sens(mut self, mouse_sens: f32) -> Self {
+        self.mouse_sens = mouse_sens;
+        self
+    }
+
+    pub fn with_center(mut self, center_position: Vec3) -> Self {
+        self.center_position = center_position;
+        self
+    }
+
+    pub fn with_orbit_distance(mut self, orbit_distance: f32) -> Self {
+        self.orbit_distance = orbit_distance;
+        self
+    }
+
+    pub fn with_distance_limits(mut self, min_distance: f32, max_distance: f32) -> Self {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn with_pan_sens(mut self, pan_sens: f32) -> Self {
+        self.pan_sens = pan_sens;
+        self
+    }
+
+    pub fn with_rotation(mut self, pitch: f32, yaw: f32) -> Self {
+        self.pitch = pitch;
+        self.yaw = yaw;
+        self.front = front_from_angles(pitch, yaw);
+        self
+    }
+
+    pub fn focus_on(&mut self, point: Vec3) {
+        // moves the center to point over the next few frames instead of
+        // jumping there. panning cancels it.
+        self.focus_target = Some(point);
+    }
+
+    pub fn is_focusing(&self) -> bool {
+        self.focus_target.is_some()
+    }
+
+    fn pan(&mut self, x: f32, y: f32) {
+        // the eye looks along -front, so that's what screen right and up are
+        // relative to. dragging moves the scene with the mouse, so the center
+        // goes the other way.
+        let view_dir = -self.front;
+        let screen_right = normalize(&view_dir.cross(&self.world_up));
+        let screen_up = screen_right.cross(&view_dir);
+        let scale = self.pan_sens * self.orbit_distance;
+
+        self.center_position += (screen_up * y - screen_right * x) * scale;
+        self.focus_target = None;
+    }
+
+    fn step_focus(&mut self, delta: f32) {
+        if let Some(target) = self.focus_target {
+            // exponential smoothing, so the speed doesn't depend on the frame
+            // rate
+            let t = 1.0 - (-self.focus_speed * delta).exp();
+            self.center_position += (target - self.center_position) * t;
+
+            if distance(&self.center_position, &target) < self.orbit_distance * 0.001 {
+                self.center_position = target;
+                self.focus_target = None;
+            }
+        }
+    }
+}
+
+impl Camera for OrbitCamera {
+    fn update(&mut self, frame_info: FrameInfo) {
+        let input = self.input.process(&frame_info);
+        self.apply_input(&input);
+    }
+
+    fn apply_input(&mut self, input: &CameraInput) {
+        self.orbit_distance = (self.orbit_distance + input.zoom)
+            .max(self.min_distance)
+            .min(self.max_distance);
+
+        // TODO: a lot of the stuff stored in OrbitCamera doesn't need to be
+        // stored across frames
+        let [x, y] = input.look;
+
+        // middle mouse or shift + mouse pans instead of rotating
+        if input.pan || input.modifiers.shift {
+            self.pan(x, y);
+        } else {
+            self.pitch = clamp_pitch(self.pitch - y * self.mouse_sens);
+            self.yaw += x * self.mouse_sens;
+        }
+
+        self.step_focus(input.delta);
+
+        // recompute front vector
+        self.front = front_from_angles(self.pitch, self.yaw);
+        self.right = normalize(&Vec3::cross(&self.front, &self.world_up));
+
+        // recompute view matrix, the projection is built on demand
+        let farther_front = self.front * self.orbit_distance;
+        self.view_mat = look_at(
+            &(self.center_position + farther_front),
+            &self.center_position,
+            &self.up,
+        );
+
+        self.aspect_ratio = aspect_ratio(input.dimensions);
+    }
+
+    fn view_matrix(&self) -> Mat4 {
+        self.view_mat
+    }
+
+    fn projection_matrix(&self) -> Mat4 {
+        self.projection.matrix(self.aspect_ratio, self.depth)
+    }
+
+    fn position(&self) -> Vec3 {
+        self.center_position + self.front * self.orbit_distance
+    }
+}
+
+pub struct FlyCamera {
+    pub position: Vec3,
+    pub front: Vec3,
+    up: Vec3,
+    right: Vec3,
+    world_up: Vec3,
+    // pitch and yaw are in radians
+    pub pitch: f32,
+    pub yaw: f32,
+    // roll is also in radians, and stays 0 unless allow_roll is set
+    pub roll: f32,
+    pub allow_roll: bool,
+    // radians per second
+    pub roll_speed: f32,
+    // top speed in units per second. scrolling changes it.
+    pub movement_speed: f32,
+    pub velocity: Vec3,
+    // how quickly the camera gets up to speed and how quickly it coasts to a
+    // stop once the keys are let go. both are rates per second, higher is
+    // snappier, and infinity turns inertia off.
+    pub acceleration: f32,
+    pub damping: f32,
+    pub mouse_sens: f32,
+    pub projection: Projection,
+    pub depth: DepthMode,
+    input: InputTracker,
+    aspect_ratio: f32,
+    view_mat: Mat4,
+}
+
+impl FlyCamera {
+    pub fn default() -> Self {
+        let pitch: f32 = 0.0;
+        let yaw: f32 = std::f32::consts::PI / 2.0;
+
+        Self {
+            position: vec3(0.0, 0.0, 0.0),
+            front: front_from_angles(pitch, yaw),
+            up: vec3(0.0, 1.0, 0.0),
+            right: vec3(0.0, 0.0, 0.0),
+            world_up: vec3(0.0, 1.0, 0.0),
+            pitch,
+            yaw,
+            roll: 0.0,
+            allow_roll: false,
+            roll_speed: 1.5,
+            movement_speed: 20.0,
+            velocity: vec3(0.0, 0.0, 0.0),
+            acceleration: 10.0,
+            damping: 6.0,
+            mouse_sens: 0.0007,
+            projection: Projection::default(),
+            depth: DepthMode::default(),
+            input: InputTracker::new(),
+            aspect_ratio: 1.0,
+            view_mat: Mat4::identity(),
+        }
+    }
+
+    // builders, every one of these is also a public field that can be changed
+    // between frames
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_depth_mode(mut self, depth: DepthMode) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        self.projection.set_fov(fov);
+        self
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.projection.set_clip_planes(near, far);
+        self
+    }
+
+    pub fn with_mouse_sens(mut self, mouse_sens: f32) -> Self {
+        self.mouse_sens = mouse_sens;
+        self
+    }
+
+    pub fn with_movement_speed(mut self, movement_speed: f32) -> Self {
+        self.movement_speed = movement_speed;
+        self
+    }
+
+    pub fn with_inertia(mut self, acceleration: f32, damping: f32) -> Self {
+        self.acceleration = acceleration;
+        self.damping = damping;
+        self
+    }
+
+    pub fn with_roll(mut self, allow_roll: bool) -> Self {
+        self.allow_roll = allow_roll;
+        self
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_rotation(mut self, pitch: f32, yaw: f32) -> Self {
+        self.pitch = pitch;
+        self.yaw = yaw;
+        self.front = front_from_angles(pitch, yaw);
+        self
+    }
+
+    pub fn move_forward(&mut self, delta: f32) {
+        self.position += self.front * self.movement_speed * delta;
+    }
+
+    pub fn move_backward(&mut self, delta: f32) {
+        self.position -= self.front * self.movement_speed * delta;
+    }
+
+    pub fn move_left(&mut self, delta: f32) {
+        self.position -= self.right * self.movement_speed * delta;
+    }
+
+    pub fn move_right(&mut self, delta: f32) {
+        self.position += self.right * self.movement_speed * delta;
+    }
+
+    pub fn move_up(&mut self, delta: f32) {
+        self.position += self.world_up * self.movement_speed * delta;
+    }
+}
+
+impl Camera for FlyCamera {
+    fn update(&mut self, frame_info: FrameInfo) {
+        let input = self.input.process(&frame_info);
+        self.apply_input(&input);
+    }
+
+    fn apply_input(&mut self, input: &CameraInput) {
+        let [x, y] = input.look;
+
+        self.pitch = clamp_pitch(self.pitch - y * self.mouse_sens);
+        self.yaw += x * self.mouse_sens;
+
+        if self.allow_roll {
+            self.roll += input.roll * self.roll_speed * input.delta;
+        }
+
+        // every line scrolled changes the top speed by 10%
+        self.movement_speed = (self.movement_speed * 1.1_f32.powf(input.zoom))
+            .max(0.1)
+            .min(10_000.0);
+
+        // accelerate towards the velocity the keys ask for. with nothing held
+        // that's zero, so the camera coasts to a stop instead. forward and
+        // right follow the view, up is always world up.
+        let mut wish_dir = self.front * input.movement.z
+            + self.right * input.movement.x
+            + self.world_up * input.movement.y;
+        if length(&wish_dir) > 1.0 {
+            wish_dir = normalize(&wish_dir);
+        }
+        let top_speed = if input.sprint {
+            self.movement_speed * 3.0
+        } else {
+            self.movement_speed
+        };
+        let target_velocity = wish_dir * top_speed;
+        let rate = if length(&wish_dir) > 0.0 {
+            self.acceleration
+        } else {
+            self.damping
+        };
+        // exponential smoothing, so it behaves the same at any frame rate
+        let t = if input.delta > 0.0 {
+            1.0 - (-rate * input.delta).exp()
+        } else {
+            0.0
+        };
+        self.velocity += (target_velocity - self.velocity) * t;
+        self.position += self.velocity * input.delta;
+
+        // update front and right
+        self.front = front_from_angles(self.pitch, self.yaw);
+        self.right = normalize(&Vec3::cross(&self.front, &self.world_up));
+
+        // rolling turns the up vector around the view direction
+        self.up = if self.roll != 0.0 {
+            rotate_vec3(&self.world_up, self.roll, &self.front)
+        } else {
+            self.world_up
+        };
+
+        self.view_mat = look_at(&self.position, &(self.position + self.front), &self.up);
+
+        self.aspect_ratio = aspect_ratio(input.dimensions);
+    }
+
+    fn view_matrix(&self) -> Mat4 {
+        self.view_mat
+    }
+
+    fn projection_matrix(&self) -> Mat4 {
+        self.projection.matrix(self.aspect_ratio, self.depth)
+    }
+
+    fn position(&self) -> Vec3 {
+        self.position
+    }
+}
+
+fn clamp_pitch(pitch: f32) -> f32 {
+    // looking straight up or down makes look_at degenerate, so stop just
+    // short of it
+    let halfpi = std::f32::consts::PI / 2.0;
+    let margin = 0.01;
+    let max_pitch = halfpi - margin;
+
+    pitch.max(-max_pitch).min(max_pitch)
+}
+
+fn front_from_angles(pitch: f32, yaw: f32) -> Vec3 {
+    normalize(&vec3(
+        pitch.cos() * yaw.cos(),
+        pitch.sin(),
+        pitch.cos() * yaw.sin(),
+    ))
+}
+
+pub fn aspect_ratio(dimensions: [u32; 2]) -> f32 {
+    (dimensions[0] as f32) / (dimensions[1] as f32)
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct CameraData {
+    view: CameraMatrix,
+    proj: CameraMatrix,
+    pos: [f32; 3],
+}
+impl Data for CameraData {}
+
+impl CameraData {
+    pub fn new(view: Mat4, proj: Mat4, pos: Vec3) -> Self {
+        Self {
+            view: view.into(),
+            proj: proj.into(),
+            pos: pos.into(),
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::from(self.view)
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        Mat4::from(self.proj)
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
+
+pub type CameraMatrix = [[f32; 4]; 4];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: f32 = 1.0 / 60.0;
+
+    fn frame(delta: f32) -> CameraInput {
+        CameraInput {
+            delta,
+            ..CameraInput::default()
+        }
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            distance(&actual, &expected) < 1e-4,
+            "got {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn orbit_zoom_clamps() {
+        let mut camera = OrbitCamera::default().with_distance_limits(1.0, 50.0);
+
+        camera.apply_input(&CameraInput {
+            zoom: -100.0,
+            ..frame(DELTA)
+        });
+        assert_eq!(camera.orbit_distance, 1.0);
+
+        camera.apply_input(&CameraInput {
+            zoom: 1_000.0,
+            ..frame(DELTA)
+        });
+        assert_eq!(camera.orbit_distance, 50.0);
+    }
+
+    #[test]
+    fn orbit_pan_moves_center() {
+        // the default camera sits at +z looking at the origin, so screen
+        // right is +x and screen up is +y
+        let mut camera = OrbitCamera::default();
+        let offset = camera.position() - camera.center_position;
+
+        camera.apply_input(&CameraInput {
+            look: [10.0, 10.0],
+            pan: true,
+            ..frame(DELTA)
+        });
+
+        // 10 pixels * pan_sens * orbit distance, against the mouse in x and
+        // with it in y since mouse y points down
+        assert_near(camera.center_position, vec3(-0.2, 0.2, 0.0));
+        // the eye moves along, it doesn't turn
+        assert_near(camera.position() - camera.center_position, offset);
+    }
+
+    #[test]
+    fn orbit_focus_converges() {
+        let target = vec3(10.0, 0.0, 0.0);
+        let mut camera = OrbitCamera::default();
+        camera.focus_on(target);
+
+        camera.apply_input(&frame(DELTA));
+        assert!(camera.is_focusing());
+        assert!(camera.center_position.x > 0.0 && camera.center_position.x < 10.0);
+
+        for _ in 0..120 {
+            camera.apply_input(&frame(DELTA));
+        }
+        assert!(!camera.is_focusing());
+        assert_eq!(camera.center_position, target);
+    }
+
+    #[test]
+    fn orbit_pan_cancels_focus() {
+        let mut camera = OrbitCamera::default();
+        camera.focus_on(vec3(10.0, 0.0, 0.0));
+
+        camera.apply_input(&CameraInput {
+            look: [1.0, 0.0],
+            pan: true,
+            ..frame(DELTA)
+        });
+        assert!(!camera.is_focusing());
+    }
+
+    #[test]
+    fn fly_up_and_down() {
+        // E and Q turn into movement.y. without inertia the camera moves at
+        // full speed straight away, and always along world up, even when
+        // looking down.
+        let mut camera = FlyCamera::default()
+            .with_rotation(-1.0, 0.0)
+            .with_inertia(std::f32::INFINITY, std::f32::INFINITY);
+
+        camera.apply_input(&CameraInput {
+            movement: vec3(0.0, 1.0, 0.0),
+            ..frame(0.5)
+        });
+        assert_near(camera.position, vec3(0.0, 10.0, 0.0));
+
+        camera.apply_input(&CameraInput {
+            movement: vec3(0.0, -1.0, 0.0),
+            ..frame(0.25)
+        });
+        assert_near(camera.position, vec3(0.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn fly_inertia_comes_to_rest() {
+        let mut camera = FlyCamera::default();
+
+        // hold forward until the camera is at top speed
+        for _ in 0..120 {
+            camera.apply_input(&CameraInput {
+                movement: vec3(0.0, 0.0, 1.0),
+                ..frame(DELTA)
+            });
+        }
+        assert!((length(&camera.velocity) - camera.movement_speed).abs() < 1e-2);
+
+        // then let go. it keeps coasting for a while, slowing down every frame
+        let mut speed = length(&camera.velocity);
+        for _ in 0..300 {
+            camera.apply_input(&frame(DELTA));
+            let new_speed = length(&camera.velocity);
+
+            assert!(new_speed < speed);
+            speed = new_speed;
+        }
+        assert!(speed < 1e-3);
+
+        // and once it has stopped, it stays put
+        let resting = camera.position;
+        camera.apply_input(&frame(DELTA));
+        assert!(distance(&resting, &camera.position) < 1e-4);
+    }
+}
@@ -0,0 +1,308 @@
+use render_engine::input::FrameInfo;
+
+use serde::{Deserialize, Serialize};
+
+use nalgebra_glm::*;
+
+use std::fs;
+use std::path::Path;
+
+use crate::camera::{aspect_ratio, Camera, CameraData};
+use crate::error::LoadError;
+use crate::input::{CameraInput, InputTracker};
+use crate::projection::{DepthMode, Projection};
+
+// an authored camera flythrough: the camera passes through every keyframe's
+// position at its time, looking at its target. paths are stored as RON, see
+// paths/sponza.ron for an example.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+    #[serde(default)]
+    pub spline: SplineKind,
+    #[serde(default)]
+    pub easing: Easing,
+    // start over after the last keyframe instead of stopping there
+    #[serde(default)]
+    pub looping: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Keyframe {
+    // in seconds from the start of the path
+    pub time: f32,
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SplineKind {
+    // uniform Catmull-Rom. smooth, but overshoots and can loop on itself where
+    // keyframes are unevenly spaced.
+    CatmullRom,
+    // centripetal Catmull-Rom, which never forms loops or cusps within a
+    // segment and stays closer to the straight line between keyframes
+    Centripetal,
+}
+
+impl Default for SplineKind {
+    fn default() -> Self {
+        SplineKind::Centripetal
+    }
+}
+
+// how the view direction turns from one keyframe's to the next
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    // starts and stops turning gently, so the camera settles on every target
+    Smooth,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Smooth
+    }
+}
+
+impl CameraPath {
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        let text = fs::read_to_string(path).map_err(|e| LoadError::from_io(path, e))?;
+        let mut camera_path: Self = ron::de::from_str(&text).map_err(|e| LoadError::Undecodable {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+
+        if camera_path.keyframes.is_empty() {
+            return Err(LoadError::Undecodable {
+                path: path.to_path_buf(),
+                reason: "camera path has no keyframes".to_string(),
+            });
+        }
+
+        // sample relies on the keyframes being in order
+        camera_path
+            .keyframes
+            .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+        Ok(camera_path)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|key| key.time).unwrap_or(0.0)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<(Vec3, Vec3)> {
+        // returns the camera's position and the point it's looking at. times
+        // outside the path are clamped to its ends, or wrapped if it loops.
+        // load rejects paths without keyframes, but the fields are public so
+        // one can still be built by hand. those return None.
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        if keys.len() == 1 {
+            return Some((make_vec3(&first.position), make_vec3(&first.target)));
+        }
+
+        let start = first.time;
+        let end = self.duration();
+
+        let time = if self.looping && end > start {
+            start + (time - start).rem_euclid(end - start)
+        } else {
+            time.max(start).min(end)
+        };
+
+        // index of the keyframe at the start of the segment time is in
+        let idx = keys
+            .iter()
+            .rposition(|key| key.time <= time)
+            .unwrap_or(0)
+            .min(keys.len() - 2);
+
+        let (key1, key2) = (&keys[idx], &keys[idx + 1]);
+        let segment_length = key2.time - key1.time;
+        let u = if segment_length > 0.0 {
+            ((time - key1.time) / segment_length).max(0.0).min(1.0)
+        } else {
+            1.0
+        };
+
+        // the spline needs a point on each side of the segment. at the ends of
+        // the path there isn't one, so mirror the neighbour instead.
+        let p1 = make_vec3(&key1.position);
+        let p2 = make_vec3(&key2.position);
+        let p0 = if idx > 0 {
+            make_vec3(&keys[idx - 1].position)
+        } else {
+            p1 * 2.0 - p2
+        };
+        let p3 = if idx + 2 < keys.len() {
+            make_vec3(&keys[idx + 2].position)
+        } else {
+            p2 * 2.0 - p1
+        };
+
+        let alpha = match self.spline {
+            SplineKind::CatmullRom => 0.0,
+            SplineKind::Centripetal => 0.5,
+        };
+        let position = catmull_rom(p0, p1, p2, p3, u, alpha);
+
+        // the view direction is interpolated separately, so the camera turns
+        // towards the next target at its own pace
+        let dir1 = normalize(&(make_vec3(&key1.target) - p1));
+        let dir2 = normalize(&(make_vec3(&key2.target) - p2));
+        let eased = match self.easing {
+            Easing::Linear => u,
+            Easing::Smooth => u * u * (3.0 - 2.0 * u),
+        };
+        let direction = slerp_direction(&dir1, &dir2, eased);
+
+        Some((position, position + direction))
+    }
+
+    pub fn camera_data(
+        &self,
+        time: f32,
+        projection: &Projection,
+        aspect_ratio: f32,
+        depth: DepthMode,
+    ) -> Option<CameraData> {
+        let (position, target) = self.sample(time)?;
+        let view = look_at(&position, &target, &vec3(0.0, 1.0, 0.0));
+
+        Some(CameraData::new(
+            view,
+            projection.matrix(aspect_ratio, depth),
+            position,
+        ))
+    }
+}
+
+pub(crate) fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, u: f32, alpha: f32) -> Vec3 {
+    // Barry and Goldman's pyramidal formulation. alpha = 0 gives the uniform
+    // spline and alpha = 0.5 the centripetal one. u goes from 0 at p1 to 1 at
+    // p2.
+    let knot = |t: f32, a: &Vec3, b: &Vec3| -> f32 {
+        // keyframes at the same spot would give a zero-length interval and
+        // divide by zero further down
+        t + distance(a, b).powf(alpha).max(1e-4)
+    };
+    let t0 = 0.0;
+    let t1 = knot(t0, &p0, &p1);
+    let t2 = knot(t1, &p1, &p2);
+    let t3 = knot(t2, &p2, &p3);
+    let t = t1 + (t2 - t1) * u;
+
+    let a1 = p0 * ((t1 - t) / (t1 - t0)) + p1 * ((t - t0) / (t1 - t0));
+    let a2 = p1 * ((t2 - t) / (t2 - t1)) + p2 * ((t - t1) / (t2 - t1));
+    let a3 = p2 * ((t3 - t) / (t3 - t2)) + p3 * ((t - t2) / (t3 - t2));
+
+    let b1 = a1 * ((t2 - t) / (t2 - t0)) + a2 * ((t - t0) / (t2 - t0));
+    let b2 = a2 * ((t3 - t) / (t3 - t1)) + a3 * ((t - t1) / (t3 - t1));
+
+    b1 * ((t2 - t) / (t2 - t1)) + b2 * ((t - t1) / (t2 - t1))
+}
+
+fn slerp_direction(from: &Vec3, to: &Vec3, t: f32) -> Vec3 {
+    // spherical interpolation between two unit vectors, so the camera turns
+    // at a constant angular speed
+    let cos_angle = dot(from, to).max(-1.0).min(1.0);
+
+    if cos_angle > 0.9995 {
+        // close enough that lerping is indistinguishable, and slerp would
+        // divide by almost zero
+        return normalize(&(from + (to - from) * t));
+    }
+
+    let angle = cos_angle.acos();
+    let axis_to = if cos_angle < -0.9995 {
+        // facing opposite ways, any perpendicular works. turn around world up
+        // unless that's what we're looking along.
+        let side = if from.y.abs() < 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        normalize(&from.cross(&side).cross(from))
+    } else {
+        normalize(&(to - from * cos_angle))
+    };
+
+    from * (angle * t).cos() + axis_to * (angle * t).sin()
+}
+
+// plays a CameraPath back as a regular camera, advancing by each frame's
+// delta
+pub struct PathCamera {
+    pub path: CameraPath,
+    pub time: f32,
+    pub projection: Projection,
+    pub depth: DepthMode,
+    input: InputTracker,
+    aspect_ratio: f32,
+}
+
+impl PathCamera {
+    pub fn new(path: CameraPath) -> Self {
+        let time = path.keyframes.first().map(|key| key.time).unwrap_or(0.0);
+
+        Self {
+            path,
+            time,
+            projection: Projection::default(),
+            depth: DepthMode::default(),
+            input: InputTracker::new(),
+            aspect_ratio: 1.0,
+        }
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_depth_mode(mut self, depth: DepthMode) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.path.looping && self.time >= self.path.duration()
+    }
+}
+
+impl Camera for PathCamera {
+    fn update(&mut self, frame_info: FrameInfo) {
+        let input = self.input.process(&frame_info);
+        self.apply_input(&input);
+    }
+
+    fn apply_input(&mut self, input: &CameraInput) {
+        // everything but time and window size is ignored, the path decides
+        // where to go
+        self.time += input.delta;
+        self.aspect_ratio = aspect_ratio(input.dimensions);
+    }
+
+    fn view_matrix(&self) -> Mat4 {
+        // an empty path leaves the camera at the origin, looking down -z
+        let (position, target) = self
+            .path
+            .sample(self.time)
+            .unwrap_or_else(|| (vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0)));
+
+        look_at(&position, &target, &vec3(0.0, 1.0, 0.0))
+    }
+
+    fn projection_matrix(&self) -> Mat4 {
+        self.projection.matrix(self.aspect_ratio, self.depth)
+    }
+
+    fn position(&self) -> Vec3 {
+        self.path
+            .sample(self.time)
+            .map(|(position, _)| position)
+            .unwrap_or_else(|| vec3(0.0, 0.0, 0.0))
+    }
+}
@@ -0,0 +1,535 @@
+use render_engine::collection::Data;
+
+use nalgebra_glm::*;
+
+use crate::camera::CameraData;
+use crate::culling::{Aabb, Sphere};
+use crate::light::{Light, LightList, MAX_LIGHTS};
+
+// the shaders declare arrays of these sizes, see ClusterGridData and
+// ClusterIndexData
+pub const MAX_CLUSTERS: usize = 16 * 8 * 24;
+pub const MAX_CLUSTER_INDICES: usize = 8192;
+
+// splits the camera's frustum into a grid of clusters: dims[0] by dims[1]
+// tiles on screen and dims[2] slices in depth. the slices get exponentially
+// deeper further away, so every cluster is roughly as deep as it is wide.
+// each cluster gets a list of the lights that can reach it, and fragments only
+// shade those instead of every light in the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterGrid {
+    pub dims: [u32; 3],
+    // view space depth at which the second slice starts. everything nearer
+    // is in the first one.
+    pub near: f32,
+    // and where the last slice starts. everything further is in the last one.
+    pub far: f32,
+}
+
+// which lights each cluster ended up with. indices point into the LightList
+// the grid was built from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterAssignment {
+    pub dims: [u32; 3],
+    // offset into indices and light count for every cluster, x changes
+    // fastest and z slowest
+    pub grid: Vec<(u32, u32)>,
+    pub indices: Vec<u32>,
+    // true if MAX_CLUSTER_INDICES ran out and some clusters lost lights
+    pub overflowed: bool,
+    depth_scale: f32,
+    depth_bias: f32,
+    near: f32,
+    far: f32,
+}
+
+// see the ClusterGrid block in shaders/pretty/all_frag.glsl. these would be
+// storage buffers, but render-engine's collections only upload uniform
+// buffers, so the grid and the index list are packed tightly enough to fit in
+// one each. that caps the index list at MAX_CLUSTER_INDICES, past which
+// ClusterAssignment::overflowed is set.
+#[derive(Clone, Copy)]
+pub struct ClusterGridData {
+    // clusters in x, y and z, unused
+    pub dims: [f32; 4],
+    // scale and bias turning log(view depth) into a slice, near, far
+    pub depth: [f32; 4],
+    // offset << 16 | count for every cluster, four to a uvec4
+    pub grid: [[u32; 4]; MAX_CLUSTERS / 4],
+}
+
+impl Data for ClusterGridData {}
+
+// see the ClusterIndices block in shaders/pretty/all_frag.glsl
+#[derive(Clone, Copy)]
+pub struct ClusterIndexData {
+    // light indices, a byte each and sixteen to a uvec4. index i is byte
+    // i % 4 of component (i / 4) % 4 of element i / 16.
+    pub indices: [[u32; 4]; MAX_CLUSTER_INDICES / 16],
+}
+
+impl Data for ClusterIndexData {}
+
+impl Default for ClusterGrid {
+    fn default() -> Self {
+        Self {
+            dims: [16, 8, 24],
+            near: 1.0,
+            far: 500.0,
+        }
+    }
+}
+
+impl ClusterGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dimensions(mut self, x: u32, y: u32, z: u32) -> Self {
+        // there have to be at least 3 slices: one before near, one past far
+        // and one in between. slices give up resolution first if there are
+        // too many clusters.
+        let max = MAX_CLUSTERS as u32;
+        let x = x.max(1).min(max / 3);
+        let y = y.max(1).min(max / (3 * x));
+        let z = z.max(3).min(max / (x * y));
+
+        self.dims = [x, y, z];
+        self
+    }
+
+    pub fn with_depth_range(mut self, near: f32, far: f32) -> Self {
+        self.near = near.max(0.001);
+        self.far = far.max(self.near * 2.0);
+        self
+    }
+
+    pub fn cluster_count(&self) -> usize {
+        (self.dims[0] * self.dims[1] * self.dims[2]) as usize
+    }
+
+    // view space depth at which slice z starts
+    pub fn slice_depth(&self, z: u32) -> f32 {
+        if z == 0 {
+            0.0
+        } else if z >= self.dims[2] {
+            // sqrt so that squared distances to it still fit in an f32
+            std::f32::MAX.sqrt()
+        } else {
+            let slices = (self.dims[2] - 2) as f32;
+            self.near * (self.far / self.near).powf((z - 1) as f32 / slices)
+        }
+    }
+
+    // the slice a view space depth falls in, same as the shaders compute it
+    pub fn slice_at(&self, depth: f32) -> u32 {
+        let (scale, bias) = self.depth_scale_bias();
+        let slice = (depth.max(0.0001).ln() * scale + bias).floor();
+
+        slice.max(0.0).min((self.dims[2] - 1) as f32) as u32
+    }
+
+    // slice = log(depth) * scale + bias, where the second slice starts at
+    // near and the last one at far
+    fn depth_scale_bias(&self) -> (f32, f32) {
+        let slices = (self.dims[2] - 2) as f32;
+        let scale = slices / (self.far / self.near).ln();
+
+        (scale, 1.0 - self.near.ln() * scale)
+    }
+
+    // view space bounds of every cluster, in the same order as
+    // ClusterAssignment::grid. works for any projection render-engine's
+    // cameras make, including reverse-Z and infinite far planes, because only
+    // the x and y rows of the projection matrix are used.
+    pub fn cluster_bounds(&self, projection: &Mat4) -> Vec<Aabb> {
+        let [dx, dy, dz] = self.dims;
+        let orthographic = projection[(3, 3)] != 0.0;
+
+        // for perspective, ndc.x = p00 * x / -z - p02 at every depth, so a
+        // tile's edge is a plane through the eye with x / -z fixed. for
+        // orthographic it's x itself that's fixed.
+        let edge = |ndc: f32, axis: usize| -> f32 {
+            let scale = projection[(axis, axis)];
+            if orthographic {
+                (ndc - projection[(axis, 3)]) / scale
+            } else {
+                (ndc + projection[(axis, 2)]) / scale
+            }
+        };
+        let ndc = |i: u32, count: u32| -1.0 + 2.0 * i as f32 / count as f32;
+
+        let mut bounds = Vec::with_capacity(self.cluster_count());
+        for z in 0..dz {
+            let (near, far) = (self.slice_depth(z), self.slice_depth(z + 1));
+            for y in 0..dy {
+                let (y0, y1) = (edge(ndc(y, dy), 1), edge(ndc(y + 1, dy), 1));
+                for x in 0..dx {
+                    let (x0, x1) = (edge(ndc(x, dx), 0), edge(ndc(x + 1, dx), 0));
+
+                    let corners: Vec<[f32; 3]> = [near, far]
+                        .iter()
+                        .flat_map(|&depth| {
+                            let d = if orthographic { 1.0 } else { depth };
+                            vec![
+                                [x0 * d, y0 * d, -depth],
+                                [x1 * d, y0 * d, -depth],
+                                [x0 * d, y1 * d, -depth],
+                                [x1 * d, y1 * d, -depth],
+                            ]
+                        })
+                        .collect();
+                    bounds.push(Aabb::from_positions(corners));
+                }
+            }
+        }
+
+        bounds
+    }
+
+    // finds the lights that can reach each cluster. only the first
+    // MAX_LIGHTS lights are considered, the same ones LightList::get_data
+    // uploads.
+    pub fn assign(&self, camera_data: &CameraData, lights: &LightList) -> ClusterAssignment {
+        let view = camera_data.view();
+        let bounds = self.cluster_bounds(&camera_data.projection());
+
+        let volumes: Vec<LightVolume> = lights
+            .lights()
+            .take(MAX_LIGHTS)
+            .map(|light| LightVolume::new(light, &view))
+            .collect();
+
+        let mut grid = Vec::with_capacity(bounds.len());
+        let mut indices = vec![];
+        let mut overflowed = false;
+        for aabb in bounds.iter() {
+            let offset = indices.len() as u32;
+            let sphere = aabb.bounding_sphere();
+            for (idx, volume) in volumes.iter().enumerate() {
+                if !volume.intersects(aabb, &sphere) {
+                    continue;
+                }
+                if indices.len() >= MAX_CLUSTER_INDICES {
+                    overflowed = true;
+                    break;
+                }
+                indices.push(idx as u32);
+            }
+            grid.push((offset, indices.len() as u32 - offset));
+        }
+
+        let (depth_scale, depth_bias) = self.depth_scale_bias();
+
+        ClusterAssignment {
+            dims: self.dims,
+            grid,
+            indices,
+            overflowed,
+            depth_scale,
+            depth_bias,
+            near: self.near,
+            far: self.far,
+        }
+    }
+}
+
+impl ClusterAssignment {
+    pub fn cluster_index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + self.dims[0] * (y + self.dims[1] * z)) as usize
+    }
+
+    pub fn lights_in(&self, x: u32, y: u32, z: u32) -> &[u32] {
+        let (offset, count) = self.grid[self.cluster_index(x, y, z)];
+
+        &self.indices[offset as usize..(offset + count) as usize]
+    }
+
+    // the most lights any one cluster has to shade
+    pub fn max_lights_per_cluster(&self) -> u32 {
+        self.grid.iter().map(|&(_, count)| count).max().unwrap_or(0)
+    }
+
+    pub fn get_data(&self) -> (ClusterGridData, ClusterIndexData) {
+        let mut grid = [[0; 4]; MAX_CLUSTERS / 4];
+        for (idx, &(offset, count)) in self.grid.iter().enumerate().take(MAX_CLUSTERS) {
+            grid[idx / 4][idx % 4] = (offset << 16) | count;
+        }
+
+        let mut indices = [[0; 4]; MAX_CLUSTER_INDICES / 16];
+        for (idx, &light) in self.indices.iter().enumerate() {
+            indices[idx / 16][(idx / 4) % 4] |= (light & 0xff) << (8 * (idx % 4));
+        }
+
+        (
+            ClusterGridData {
+                dims: [
+                    self.dims[0] as f32,
+                    self.dims[1] as f32,
+                    self.dims[2] as f32,
+                    0.0,
+                ],
+                depth: [self.depth_scale, self.depth_bias, self.near, self.far],
+                grid,
+            },
+            ClusterIndexData { indices },
+        )
+    }
+}
+
+// the part of view space a light can reach
+enum LightVolume {
+    Sphere(Sphere),
+    // bounds is a sphere around the cone for quick rejection, sin and cos
+    // are of the outer angle
+    Cone {
+        bounds: Sphere,
+        apex: Vec3,
+        direction: Vec3,
+        range: f32,
+        sin: f32,
+        cos: f32,
+    },
+    Everywhere,
+}
+
+impl LightVolume {
+    fn new(light: &Light, view: &Mat4) -> Self {
+        let to_view = |p: &Vec3| (view * vec4(p.x, p.y, p.z, 1.0)).xyz();
+
+        match light {
+            Light::Point(light) => LightVolume::Sphere(Sphere {
+                center: to_view(&light.position),
+                radius: light.range,
+            }),
+            Light::Spot(light) => {
+                let apex = to_view(&light.position);
+                let d = light.direction;
+                let direction = normalize(&(view * vec4(d.x, d.y, d.z, 0.0)).xyz());
+                let (sin, cos) = light.outer_angle.sin_cos();
+
+                // smallest sphere around the cone: narrow cones are bounded
+                // by their tip and the rim of the base, wide ones by the base
+                // alone. the rim is range away from the tip, so a sphere
+                // through both is centred range / (2 cos) along the axis.
+                let bounds = if light.outer_angle < std::f32::consts::FRAC_PI_4 {
+                    let radius = light.range / (2.0 * cos);
+                    Sphere {
+                        center: apex + direction * radius,
+                        radius,
+                    }
+                } else {
+                    Sphere {
+                        center: apex + direction * (cos * light.range),
+                        radius: sin * light.range,
+                    }
+                };
+
+                LightVolume::Cone {
+                    bounds,
+                    apex,
+                    direction,
+                    range: light.range,
+                    sin,
+                    cos,
+                }
+            }
+            // lights everything, so it's in every cluster
+            Light::Directional(_) => LightVolume::Everywhere,
+        }
+    }
+
+    fn intersects(&self, aabb: &Aabb, cluster_sphere: &Sphere) -> bool {
+        match self {
+            LightVolume::Sphere(sphere) => sphere.intersects_aabb(aabb),
+            LightVolume::Cone {
+                bounds,
+                apex,
+                direction,
+                range,
+                sin,
+                cos,
+            } => {
+                if !bounds.intersects_aabb(aabb) {
+                    return false;
+                }
+
+                // Bart Wronski's cone test against the cluster's bounding
+                // sphere: is the sphere past the cone's side, in front of
+                // its base or behind its tip?
+                let v = cluster_sphere.center - apex;
+                let v_len_sq = dot(&v, &v);
+                let along = dot(&v, direction);
+                let sideways = (v_len_sq - along * along).max(0.0).sqrt();
+                let closest = cos * sideways - along * sin;
+
+                let radius = cluster_sphere.radius;
+                !(closest > radius || along > radius + range || along < -radius)
+            }
+            LightVolume::Everywhere => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::{PointLight, SpotLight};
+    use crate::projection::{perspective_vk, DepthMode};
+
+    // 2 by 2 tiles, with slices at depths 0, 1, 10 and 100. the camera sits at
+    // the origin looking down -z with a 90 degree field of view, so the tiles
+    // split at x = 0 and y = 0 and tile y 0 is the top half.
+    fn assign(lights: Vec<Light>) -> ClusterAssignment {
+        let grid = ClusterGrid::new()
+            .with_dimensions(2, 2, 4)
+            .with_depth_range(1.0, 100.0);
+        let proj = perspective_vk(
+            1.0,
+            std::f32::consts::FRAC_PI_2,
+            0.1,
+            1000.0,
+            DepthMode::Standard,
+        );
+        let camera_data = CameraData::new(Mat4::identity(), proj, vec3(0.0, 0.0, 0.0));
+
+        let mut list = LightList::new();
+        for light in lights {
+            list.push(light);
+        }
+
+        grid.assign(&camera_data, &list)
+    }
+
+    #[test]
+    fn slices() {
+        let grid = ClusterGrid::new()
+            .with_dimensions(2, 2, 4)
+            .with_depth_range(1.0, 100.0);
+
+        assert_eq!(grid.slice_at(0.5), 0);
+        assert_eq!(grid.slice_at(5.0), 1);
+        assert_eq!(grid.slice_at(50.0), 2);
+        assert_eq!(grid.slice_at(5000.0), 3);
+    }
+
+    #[test]
+    fn point_lights() {
+        let assignment = assign(vec![
+            // bottom left, well inside slice 1
+            PointLight::new(vec3(-5.0, -5.0, -5.0))
+                .with_range(1.0)
+                .into(),
+            // top right, across the boundary between slices 1 and 2
+            PointLight::new(vec3(5.0, 5.0, -10.0))
+                .with_range(2.0)
+                .into(),
+            // behind the camera, so in no cluster at all
+            PointLight::new(vec3(0.0, 0.0, 20.0)).with_range(5.0).into(),
+        ]);
+
+        assert_eq!(assignment.lights_in(0, 1, 1), &[0]);
+        assert_eq!(assignment.lights_in(1, 0, 1), &[1]);
+        assert_eq!(assignment.lights_in(1, 0, 2), &[1]);
+        assert_eq!(assignment.indices.len(), 3);
+        assert!(!assignment.indices.contains(&2));
+        assert_eq!(assignment.max_lights_per_cluster(), 1);
+        assert!(!assignment.overflowed);
+    }
+
+    #[test]
+    fn spot_light() {
+        // a narrow cone down the middle of the screen, reaching 50 units: the
+        // tiles all meet at its axis, so every cluster up to slice 2 has it
+        let assignment = assign(vec![
+            PointLight::new(vec3(-5.0, -5.0, -5.0))
+                .with_range(1.0)
+                .into(),
+            SpotLight::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0))
+                .with_cone(0.1, 0.2)
+                .with_range(50.0)
+                .into(),
+        ]);
+
+        for z in 0..3 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    let expected: &[u32] = if (x, y, z) == (0, 1, 1) {
+                        &[0, 1]
+                    } else {
+                        &[1]
+                    };
+                    assert_eq!(assignment.lights_in(x, y, z), expected, "{} {} {}", x, y, z);
+                }
+            }
+        }
+        for y in 0..2 {
+            for x in 0..2 {
+                assert!(assignment.lights_in(x, y, 3).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn cone_bounds() {
+        // the bounding sphere has to hold the tip, the rim and the far end of
+        // the cap, and be no bigger than it needs to be: the rim touches it
+        let range = 10.0;
+        for &angle in &[0.1, 0.5, 0.7, 0.9, 1.4] {
+            let light = SpotLight::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0))
+                .with_cone(angle * 0.5, angle)
+                .with_range(range)
+                .into();
+            let bounds = match LightVolume::new(&light, &Mat4::identity()) {
+                LightVolume::Cone { bounds, .. } => bounds,
+                _ => panic!("a spot light should have a cone volume"),
+            };
+            let dist = |p: Vec3| distance(&bounds.center, &p) - bounds.radius;
+
+            let (sin, cos) = angle.sin_cos();
+            let rim = vec3(sin * range, 0.0, -cos * range);
+            let tip = vec3(0.0, 0.0, 0.0);
+            let cap = vec3(0.0, 0.0, -range);
+            assert!(dist(rim).abs() < 1e-4, "angle {}", angle);
+            assert!(dist(tip) < 1e-4 && dist(cap) < 1e-4, "angle {}", angle);
+        }
+    }
+
+    #[test]
+    fn offsets_follow_counts() {
+        let assignment = assign(vec![
+            PointLight::new(vec3(-5.0, -5.0, -5.0))
+                .with_range(1.0)
+                .into(),
+            PointLight::new(vec3(5.0, 5.0, -10.0))
+                .with_range(2.0)
+                .into(),
+            SpotLight::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0))
+                .with_cone(0.1, 0.2)
+                .with_range(50.0)
+                .into(),
+        ]);
+
+        // clusters are stored x fastest, z slowest, each one's lights right
+        // after the previous one's
+        let mut offset = 0;
+        for (idx, &(cluster_offset, count)) in assignment.grid.iter().enumerate() {
+            assert_eq!(cluster_offset, offset, "cluster {}", idx);
+            offset += count;
+        }
+        assert_eq!(offset as usize, assignment.indices.len());
+        // slice 0 has 4 spot light entries and slice 1 has 6, then there's
+        // one more for the cluster at 0, 0, 2
+        let cluster = assignment.cluster_index(1, 0, 2);
+        assert_eq!(assignment.grid[cluster], (11, 2));
+
+        // and the packed version the shaders read says the same, see
+        // cluster_lights and light_index in all_frag.glsl
+        let (grid_data, index_data) = assignment.get_data();
+        assert_eq!(grid_data.grid[cluster / 4][cluster % 4], 11 << 16 | 2);
+        let light_index =
+            |i: usize| (index_data.indices[i / 16][(i / 4) % 4] >> (8 * (i % 4))) & 0xff;
+        assert_eq!(light_index(11), 1);
+        assert_eq!(light_index(12), 2);
+    }
+}
@@ -0,0 +1,155 @@
+use nalgebra_glm::*;
+
+use crate::camera::CameraData;
+
+// the 6 planes bounding what a view-projection matrix can see. each plane is
+// (normal, distance) with the normal pointing inwards, so a point p is inside
+// if dot(normal, p) + distance >= 0 for every plane.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_proj: &Mat4) -> Self {
+        // Gribb and Hartmann's method, adjusted for vulkan's 0..1 depth: the
+        // near plane is z >= 0 rather than z >= -w. the depth planes are the
+        // same for reverse-Z, just swapped, and with an infinite far plane the
+        // far one ends up accepting everything, which is what we want.
+        let row = |i: usize| -> Vec4 {
+            vec4(
+                view_proj[(i, 0)],
+                view_proj[(i, 1)],
+                view_proj[(i, 2)],
+                view_proj[(i, 3)],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z];
+
+        // normalizing makes the distances real distances, which the sphere
+        // test needs
+        let mut normalized = [vec4(0.0, 0.0, 0.0, 0.0); 6];
+        for (out, plane) in normalized.iter_mut().zip(planes.iter()) {
+            let length = length(&plane.xyz());
+            *out = if length > 0.0 { plane / length } else { *plane };
+        }
+
+        Self { planes: normalized }
+    }
+
+    pub fn from_camera_data(camera_data: &CameraData) -> Self {
+        Self::from_matrix(&camera_data.view_projection())
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        // for each plane only the corner furthest along its normal matters. if
+        // even that one is outside, the whole box is.
+        self.planes.iter().all(|plane| {
+            let corner = vec3(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+
+            dot(&plane.xyz(), &corner) + plane.w >= 0.0
+        })
+    }
+
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| dot(&plane.xyz(), &sphere.center) + plane.w >= -sphere.radius)
+    }
+}
+
+// axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_positions<I: IntoIterator<Item = [f32; 3]>>(positions: I) -> Self {
+        // an empty list gives an inverted box, which nothing intersects
+        let inf = std::f32::INFINITY;
+        let mut aabb = Self {
+            min: vec3(inf, inf, inf),
+            max: vec3(-inf, -inf, -inf),
+        };
+
+        for p in positions {
+            let p = make_vec3(&p);
+            aabb.min = min2(&aabb.min, &p);
+            aabb.max = max2(&aabb.max, &p);
+        }
+
+        aabb
+    }
+
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        // the box around all 8 transformed corners, so it stays conservative
+        // under rotation
+        let corners = (0..8).map(|i| {
+            let corner = vec3(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            let transformed = matrix * vec4(corner.x, corner.y, corner.z, 1.0);
+
+            [transformed.x, transformed.y, transformed.z]
+        });
+
+        Self::from_positions(corners)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere {
+            center: self.center(),
+            radius: distance(&self.min, &self.max) * 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        // the point in the box closest to the center has to be within radius
+        let closest = min2(&max2(&self.center, &aabb.min), &aabb.max);
+
+        distance2(&closest, &self.center) <= self.radius * self.radius
+    }
+}
+
+pub struct Culled<'a, T> {
+    pub visible: Vec<&'a T>,
+    pub culled: usize,
+}
+
+pub fn cull_objects<'a, T>(frustum: &Frustum, objects: &'a [T], bounds: &[Aabb]) -> Culled<'a, T> {
+    // bounds[i] is objects[i]'s box in world space. call this before handing
+    // objects to the system, it has no idea what's on screen.
+    debug_assert_eq!(objects.len(), bounds.len(), "every object needs bounds");
+
+    let visible: Vec<&T> = objects
+        .iter()
+        .zip(bounds.iter())
+        .filter(|(_, aabb)| frustum.intersects_aabb(aabb))
+        .map(|(object, _)| object)
+        .collect();
+    let culled = objects.len() - visible.len();
+
+    Culled { visible, culled }
+}
@@ -0,0 +1,853 @@
+/*
+Environment lighting: loads HDR environment maps (Radiance .hdr or OpenEXR),
+turns them into cubemaps and prefilters them on the CPU for image based
+lighting. Everything ends up as an Image so it can go straight into an object's
+collection:
+- environment: the unfiltered cubemap, for drawing a skybox
+- irradiance: diffuse irradiance as 9 spherical harmonics coefficients, stored
+  in a 9x1 texture
+- specular: GGX-prefiltered cubemap, roughness goes from 0 at mip 0 to 1 at the
+  last mip
+- brdf_lut: the split-sum scale and bias, indexed by (n dot v, roughness)
+ */
+
+use render_engine::collection::Data;
+use render_engine::{Format, Image, Queue};
+
+use vulkano::image::Dimensions;
+
+use nalgebra_glm::*;
+
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::error::LoadError;
+use crate::texture::{dimensions_2d, upload_levels, ContainerError};
+
+#[derive(Clone)]
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    // linear RGB, row by row from the top
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl HdrImage {
+    pub fn sample_equirect(&self, dir: &Vec3) -> [f32; 3] {
+        // samples the image as a latitude-longitude map, +y is up
+        let dir = normalize(dir);
+        let u = 0.5 + dir.z.atan2(dir.x) / (2.0 * PI);
+        let v = dir.y.max(-1.0).min(1.0).acos() / PI;
+
+        self.sample_bilinear(u, v)
+    }
+
+    fn sample_bilinear(&self, u: f32, v: f32) -> [f32; 3] {
+        // u wraps around, v is clamped
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5)
+            .max(0.0)
+            .min((self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let wrap = |x: f32| -> usize { (x as i64).rem_euclid(self.width as i64) as usize };
+        let (x0, x1) = (wrap(x0), wrap(x0 + 1.0));
+        let (y0, y1) = (y0 as usize, (y0 as usize + 1).min(self.height - 1));
+
+        let p00 = self.pixels[y0 * self.width + x0];
+        let p10 = self.pixels[y0 * self.width + x1];
+        let p01 = self.pixels[y1 * self.width + x0];
+        let p11 = self.pixels[y1 * self.width + x1];
+
+        let mut result = [0.0; 3];
+        for ch in 0..3 {
+            let top = p00[ch] * (1.0 - fx) + p10[ch] * fx;
+            let bottom = p01[ch] * (1.0 - fx) + p11[ch] * fx;
+            result[ch] = top * (1.0 - fy) + bottom * fy;
+        }
+
+        result
+    }
+}
+
+pub fn load_hdr_image(path: &Path) -> Result<HdrImage, LoadError> {
+    // picks the loader based on the magic number
+    let bytes = fs::read(path).map_err(|e| LoadError::from_io(path, e))?;
+
+    if bytes.starts_with(b"#?") {
+        parse_radiance(&bytes)
+    } else if bytes.starts_with(&[0x76, 0x2f, 0x31, 0x01]) {
+        parse_exr(&bytes)
+    } else {
+        Err(ContainerError::InvalidHeader(
+            "not a Radiance HDR or OpenEXR file",
+        ))
+    }
+    .map_err(|e| LoadError::from_container(path, e))
+}
+
+pub fn parse_radiance(bytes: &[u8]) -> Result<HdrImage, ContainerError> {
+    // the header is text lines ending in an empty line, then a resolution line
+    // like "-Y 512 +X 1024", then the scanlines
+    let mut pos = 0;
+    let next_line = |pos: &mut usize| -> Result<String, ContainerError> {
+        let start = *pos;
+        let len = bytes[start..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or(ContainerError::Truncated)?;
+        *pos = start + len + 1;
+        Ok(String::from_utf8_lossy(&bytes[start..start + len]).into_owned())
+    };
+
+    loop {
+        let line = next_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(ContainerError::UnsupportedFormat(line));
+        }
+    }
+
+    let resolution = next_line(&mut pos)?;
+    let parts: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match parts.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>()
+                .map_err(|_| ContainerError::InvalidHeader("bad height"))?,
+            w.parse::<usize>()
+                .map_err(|_| ContainerError::InvalidHeader("bad width"))?,
+        ),
+        _ => {
+            return Err(ContainerError::UnsupportedFormat(format!(
+                "image orientation {:?}",
+                resolution
+            )))
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        pos = read_rgbe_scanline(bytes, pos, &mut scanline)?;
+        pixels.extend(scanline.iter().map(rgbe_to_float));
+    }
+
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn read_rgbe_scanline(
+    bytes: &[u8],
+    mut pos: usize,
+    scanline: &mut [[u8; 4]],
+) -> Result<usize, ContainerError> {
+    // returns the position right after the scanline
+    let width = scanline.len();
+    let header = bytes.get(pos..pos + 4).ok_or(ContainerError::Truncated)?;
+
+    // new-style RLE: 2, 2, then the width as a big endian u16, then each
+    // channel run-length encoded separately
+    let is_rle = header[0] == 2
+        && header[1] == 2
+        && header[2] & 0x80 == 0
+        && (8..0x8000).contains(&width);
+    if !is_rle {
+        // flat pixels
+        for pixel in scanline.iter_mut() {
+            let data = bytes.get(pos..pos + 4).ok_or(ContainerError::Truncated)?;
+            pixel.copy_from_slice(data);
+            pos += 4;
+        }
+        return Ok(pos);
+    }
+
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return Err(ContainerError::InvalidHeader("scanline width mismatch"));
+    }
+    pos += 4;
+
+    for ch in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(pos).ok_or(ContainerError::Truncated)? as usize;
+            pos += 1;
+            if count > 128 {
+                // a run of the same value
+                let count = count - 128;
+                let value = *bytes.get(pos).ok_or(ContainerError::Truncated)?;
+                pos += 1;
+                if x + count > width {
+                    return Err(ContainerError::InvalidHeader("run goes past scanline"));
+                }
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[ch] = value;
+                }
+                x += count;
+            } else {
+                // count literal values
+                if count == 0 || x + count > width {
+                    return Err(ContainerError::InvalidHeader("bad literal run"));
+                }
+                let values = bytes
+                    .get(pos..pos + count)
+                    .ok_or(ContainerError::Truncated)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[ch] = *value;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok(pos)
+}
+
+fn rgbe_to_float(rgbe: &[u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    // the +8 accounts for the mantissas being 8 bit integers
+    let scale = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+
+    [
+        rgbe[0] as f32 * scale,
+        rgbe[1] as f32 * scale,
+        rgbe[2] as f32 * scale,
+    ]
+}
+
+pub fn parse_exr(bytes: &[u8]) -> Result<HdrImage, ContainerError> {
+    // only handles uncompressed single-part scanline files, which is what
+    // most tools write when asked for "no compression". the header is a list
+    // of (name, type, size, value) attributes ending in an empty name.
+    let version = read_u32(bytes, 4)?;
+    if version & 0xff != 2 || version & 0x1a00 != 0 {
+        return Err(ContainerError::UnsupportedFormat(
+            "only single-part scanline OpenEXR files are supported".to_string(),
+        ));
+    }
+
+    let mut pos = 8;
+    let mut channels: Vec<(String, u32)> = vec![];
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = read_cstr(bytes, &mut pos)?;
+        if name.is_empty() {
+            break;
+        }
+        let _type_name = read_cstr(bytes, &mut pos)?;
+        let size = read_u32(bytes, pos)? as usize;
+        pos += 4;
+        let value = bytes
+            .get(pos..pos + size)
+            .ok_or(ContainerError::Truncated)?;
+
+        match name.as_str() {
+            "channels" => {
+                // name, pixel type (0 = uint, 1 = half, 2 = float), then 12
+                // bytes we don't care about
+                let mut cpos = 0;
+                loop {
+                    let channel_name = read_cstr(value, &mut cpos)?;
+                    if channel_name.is_empty() {
+                        break;
+                    }
+                    let pixel_type = read_u32(value, cpos)?;
+                    cpos += 16;
+                    channels.push((channel_name, pixel_type));
+                }
+            }
+            "compression" => compression = Some(*value.first().ok_or(ContainerError::Truncated)?),
+            "dataWindow" => {
+                data_window = Some([
+                    read_u32(value, 0)? as i32,
+                    read_u32(value, 4)? as i32,
+                    read_u32(value, 8)? as i32,
+                    read_u32(value, 12)? as i32,
+                ])
+            }
+            _ => {}
+        }
+        pos += size;
+    }
+
+    if compression != Some(0) {
+        return Err(ContainerError::UnsupportedFormat(format!(
+            "OpenEXR compression {:?}",
+            compression
+        )));
+    }
+    let [x_min, y_min, x_max, y_max] =
+        data_window.ok_or(ContainerError::InvalidHeader("no dataWindow"))?;
+    // the window is inclusive, so an empty or inverted one, or one too big
+    // for an i32, is broken
+    let extent = |min: i32, max: i32| {
+        max.checked_sub(min)
+            .and_then(|d| d.checked_add(1))
+            .filter(|size| *size > 0)
+            .map(|size| size as usize)
+            .ok_or(ContainerError::InvalidHeader("bad dataWindow"))
+    };
+    let width = extent(x_min, x_max)?;
+    let height = extent(y_min, y_max)?;
+
+    let channel_index = |name: &str| channels.iter().position(|(n, _)| n == name);
+    let rgb = match (channel_index("R"), channel_index("G"), channel_index("B")) {
+        (Some(r), Some(g), Some(b)) => [r, g, b],
+        _ => {
+            return Err(ContainerError::UnsupportedFormat(
+                "OpenEXR file without R, G and B channels".to_string(),
+            ))
+        }
+    };
+    let channel_sizes: Vec<usize> = channels
+        .iter()
+        .map(|(_, pixel_type)| if *pixel_type == 1 { 2 } else { 4 })
+        .collect();
+
+    // every pixel has to be somewhere in the file, so a header claiming more
+    // than that is lying. checked before allocating room for them.
+    let pixel_size: usize = channel_sizes.iter().sum();
+    let remaining = bytes.len().saturating_sub(pos);
+    let fits = width
+        .checked_mul(height)
+        .and_then(|count| count.checked_mul(pixel_size))
+        .map_or(false, |size| size <= remaining);
+    if !fits {
+        return Err(ContainerError::Truncated);
+    }
+
+    // after the header comes one offset per scanline, then the scanlines.
+    // within a scanline, each channel is stored in full before the next.
+    let mut pixels = vec![[0.0; 3]; width * height];
+    for line in 0..height {
+        let offset = read_u64(bytes, pos + line * 8)? as usize;
+        let y = read_u32(bytes, offset)? as i32 - y_min;
+        if y < 0 || y as usize >= height {
+            return Err(ContainerError::InvalidHeader("scanline out of range"));
+        }
+        let row = y as usize * width;
+
+        let mut channel_start = offset + 8;
+        for (idx, (_, pixel_type)) in channels.iter().enumerate() {
+            if let Some(ch) = rgb.iter().position(|c| *c == idx) {
+                for x in 0..width {
+                    let value_pos = channel_start + x * channel_sizes[idx];
+                    pixels[row + x][ch] = match pixel_type {
+                        1 => half_to_f32(read_u16(bytes, value_pos)?),
+                        2 => f32::from_bits(read_u32(bytes, value_pos)?),
+                        _ => read_u32(bytes, value_pos)? as f32,
+                    };
+                }
+            }
+            channel_start += width * channel_sizes[idx];
+        }
+    }
+
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * std::f32::INFINITY,
+        31 => std::f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn read_cstr(bytes: &[u8], pos: &mut usize) -> Result<String, ContainerError> {
+    let len = bytes
+        .get(*pos..)
+        .ok_or(ContainerError::Truncated)?
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(ContainerError::Truncated)?;
+    let s = String::from_utf8_lossy(&bytes[*pos..*pos + len]).into_owned();
+    *pos += len + 1;
+    Ok(s)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ContainerError> {
+    let b = bytes
+        .get(offset..offset + 2)
+        .ok_or(ContainerError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ContainerError> {
+    let b = bytes
+        .get(offset..offset + 4)
+        .ok_or(ContainerError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ContainerError> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(low | (high << 32))
+}
+
+// cubemaps
+
+#[derive(Clone)]
+pub struct Cubemap {
+    pub size: usize,
+    // +x, -x, +y, -y, +z, -z, the same order Vulkan uses for layers
+    pub faces: Vec<Vec<[f32; 3]>>,
+}
+
+impl Cubemap {
+    pub fn from_fn<F: Fn(&Vec3) -> [f32; 3]>(size: usize, f: F) -> Self {
+        // builds a cubemap by evaluating f for the direction through the
+        // center of every texel
+        let faces = (0..6)
+            .map(|face| {
+                let mut texels = Vec::with_capacity(size * size);
+                for y in 0..size {
+                    for x in 0..size {
+                        texels.push(f(&texel_direction(face, x, y, size)));
+                    }
+                }
+                texels
+            })
+            .collect();
+
+        Self { size, faces }
+    }
+
+    pub fn sample(&self, dir: &Vec3) -> [f32; 3] {
+        // nearest-texel lookup, which is plenty for prefiltering
+        let (face, u, v) = direction_to_face_uv(dir);
+        let x = ((u * self.size as f32) as usize).min(self.size - 1);
+        let y = ((v * self.size as f32) as usize).min(self.size - 1);
+
+        self.faces[face][y * self.size + x]
+    }
+
+    fn to_rgba32f_bytes(&self) -> Vec<u8> {
+        self.faces
+            .iter()
+            .flat_map(|face| face.iter())
+            .flat_map(|texel| rgba_bytes([texel[0], texel[1], texel[2], 1.0]))
+            .collect()
+    }
+}
+
+pub fn equirect_to_cubemap(image: &HdrImage, size: usize) -> Cubemap {
+    Cubemap::from_fn(size, |dir| image.sample_equirect(dir))
+}
+
+fn texel_direction(face: usize, x: usize, y: usize, size: usize) -> Vec3 {
+    // u and v go from -1 to 1 across the face, v pointing down
+    let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+
+    let dir = match face {
+        0 => vec3(1.0, -v, -u),
+        1 => vec3(-1.0, -v, u),
+        2 => vec3(u, 1.0, v),
+        3 => vec3(u, -1.0, -v),
+        4 => vec3(u, -v, 1.0),
+        _ => vec3(-u, -v, -1.0),
+    };
+
+    normalize(&dir)
+}
+
+fn direction_to_face_uv(dir: &Vec3) -> (usize, f32, f32) {
+    // the inverse of texel_direction, u and v go from 0 to 1
+    let abs = vec3(dir.x.abs(), dir.y.abs(), dir.z.abs());
+    let (face, u, v, major) = if abs.x >= abs.y && abs.x >= abs.z {
+        if dir.x > 0.0 {
+            (0, -dir.z, -dir.y, abs.x)
+        } else {
+            (1, dir.z, -dir.y, abs.x)
+        }
+    } else if abs.y >= abs.z {
+        if dir.y > 0.0 {
+            (2, dir.x, dir.z, abs.y)
+        } else {
+            (3, dir.x, -dir.z, abs.y)
+        }
+    } else if dir.z > 0.0 {
+        (4, dir.x, -dir.y, abs.z)
+    } else {
+        (5, -dir.x, -dir.y, abs.z)
+    };
+
+    (face, (u / major + 1.0) * 0.5, (v / major + 1.0) * 0.5)
+}
+
+fn texel_solid_angle(x: usize, y: usize, size: usize) -> f32 {
+    // exact solid angle of a cubemap texel, from the area of its projection
+    // onto the unit sphere
+    let area = |x: f32, y: f32| -> f32 { (x * y).atan2((x * x + y * y + 1.0).sqrt()) };
+    let texel = 2.0 / size as f32;
+    let x0 = x as f32 * texel - 1.0;
+    let y0 = y as f32 * texel - 1.0;
+    let (x1, y1) = (x0 + texel, y0 + texel);
+
+    area(x0, y0) - area(x0, y1) - area(x1, y0) + area(x1, y1)
+}
+
+// diffuse irradiance
+
+// irradiance coefficients for the first 3 bands, already convolved with the
+// cosine lobe. a shader gets the irradiance for normal n by summing
+// coefficients[i] * Y_i(n), see sh_basis for the order of Y_i.
+#[derive(Clone, Copy, Debug)]
+pub struct ShIrradiance {
+    pub coefficients: [[f32; 4]; 9],
+}
+
+impl Data for ShIrradiance {}
+
+impl ShIrradiance {
+    pub fn evaluate(&self, normal: &Vec3) -> [f32; 3] {
+        let basis = sh_basis(&normalize(normal));
+        let mut result = [0.0; 3];
+        for (coefficient, y) in self.coefficients.iter().zip(basis.iter()) {
+            for ch in 0..3 {
+                result[ch] += coefficient[ch] * y;
+            }
+        }
+
+        result
+    }
+}
+
+pub fn irradiance_sh(cubemap: &Cubemap) -> ShIrradiance {
+    // projects the radiance onto spherical harmonics, then scales each band
+    // by the cosine lobe's coefficients (pi, 2pi/3, pi/4)
+    let mut radiance = [[0.0f32; 3]; 9];
+    for (face_idx, face) in cubemap.faces.iter().enumerate() {
+        for y in 0..cubemap.size {
+            for x in 0..cubemap.size {
+                let dir = texel_direction(face_idx, x, y, cubemap.size);
+                let weight = texel_solid_angle(x, y, cubemap.size);
+                let texel = face[y * cubemap.size + x];
+
+                for (coefficient, basis) in radiance.iter_mut().zip(sh_basis(&dir).iter()) {
+                    for ch in 0..3 {
+                        coefficient[ch] += texel[ch] * basis * weight;
+                    }
+                }
+            }
+        }
+    }
+
+    let bands = [
+        PI,
+        2.0 * PI / 3.0,
+        2.0 * PI / 3.0,
+        2.0 * PI / 3.0,
+        PI / 4.0,
+        PI / 4.0,
+        PI / 4.0,
+        PI / 4.0,
+        PI / 4.0,
+    ];
+
+    let mut coefficients = [[0.0; 4]; 9];
+    for i in 0..9 {
+        for ch in 0..3 {
+            coefficients[i][ch] = radiance[i][ch] * bands[i];
+        }
+    }
+
+    ShIrradiance { coefficients }
+}
+
+pub fn sh_basis(dir: &Vec3) -> [f32; 9] {
+    // real spherical harmonics up to l = 2
+    let (x, y, z) = (dir.x, dir.y, dir.z);
+
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+// specular
+
+pub fn prefilter_specular(cubemap: &Cubemap, mip_count: usize, samples: usize) -> Vec<Cubemap> {
+    // convolves the environment with the GGX lobe for increasing roughness,
+    // one cubemap per mip level. uses the usual n = v = r assumption, so
+    // there's no stretching at grazing angles.
+    (0..mip_count)
+        .map(|level| {
+            let size = (cubemap.size >> level).max(1);
+            if level == 0 {
+                // roughness 0 is a perfect mirror
+                return Cubemap::from_fn(size, |dir| cubemap.sample(dir));
+            }
+
+            let roughness = level as f32 / (mip_count - 1) as f32;
+            Cubemap::from_fn(size, |normal| {
+                let mut total = [0.0; 3];
+                let mut total_weight = 0.0;
+                for i in 0..samples {
+                    let xi = hammersley(i, samples);
+                    let half = importance_sample_ggx(xi, normal, roughness);
+                    let light = 2.0 * dot(normal, &half) * half - normal;
+
+                    let n_dot_l = dot(normal, &light);
+                    if n_dot_l > 0.0 {
+                        let texel = cubemap.sample(&light);
+                        for ch in 0..3 {
+                            total[ch] += texel[ch] * n_dot_l;
+                        }
+                        total_weight += n_dot_l;
+                    }
+                }
+
+                if total_weight > 0.0 {
+                    [
+                        total[0] / total_weight,
+                        total[1] / total_weight,
+                        total[2] / total_weight,
+                    ]
+                } else {
+                    [0.0; 3]
+                }
+            })
+        })
+        .collect()
+}
+
+pub fn brdf_lut(size: usize, samples: usize) -> Vec<[f32; 2]> {
+    // the split-sum approximation's scale and bias for F0, x is n dot v and y
+    // is roughness. rows go from roughness 0 at the top to 1 at the bottom.
+    let normal = vec3(0.0, 0.0, 1.0);
+    let mut lut = Vec::with_capacity(size * size);
+
+    for y in 0..size {
+        for x in 0..size {
+            let n_dot_v = ((x as f32 + 0.5) / size as f32).max(1e-4);
+            let roughness = (y as f32 + 0.5) / size as f32;
+            let view = vec3((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+
+            let (mut scale, mut bias) = (0.0, 0.0);
+            for i in 0..samples {
+                let xi = hammersley(i, samples);
+                let half = importance_sample_ggx(xi, &normal, roughness);
+                let light = 2.0 * dot(&view, &half) * half - view;
+
+                let n_dot_l = light.z.max(0.0);
+                let n_dot_h = half.z.max(0.0);
+                let v_dot_h = dot(&view, &half).max(0.0);
+
+                if n_dot_l > 0.0 {
+                    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+                    let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+                    let fresnel = (1.0 - v_dot_h).powi(5);
+
+                    scale += (1.0 - fresnel) * g_vis;
+                    bias += fresnel * g_vis;
+                }
+            }
+
+            lut.push([scale / samples as f32, bias / samples as f32]);
+        }
+    }
+
+    lut
+}
+
+fn hammersley(i: usize, count: usize) -> Vec2 {
+    vec2(
+        i as f32 / count as f32,
+        (i as u32).reverse_bits() as f32 * 2.328_306_4e-10,
+    )
+}
+
+fn importance_sample_ggx(xi: Vec2, normal: &Vec3, roughness: f32) -> Vec3 {
+    // returns a half vector distributed according to GGX around normal
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let tangent_space = vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+
+    let up = if normal.z.abs() < 0.999 {
+        vec3(0.0, 0.0, 1.0)
+    } else {
+        vec3(1.0, 0.0, 0.0)
+    };
+    let tangent = normalize(&up.cross(normal));
+    let bitangent = normal.cross(&tangent);
+
+    normalize(&(tangent * tangent_space.x + bitangent * tangent_space.y + normal * tangent_space.z))
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    // schlick-GGX with the k used for IBL
+    let k = roughness * roughness / 2.0;
+    let g1 = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+
+    g1(n_dot_v) * g1(n_dot_l)
+}
+
+// uploading
+
+pub struct EnvironmentSettings {
+    pub cubemap_size: usize,
+    pub specular_size: usize,
+    pub specular_mips: usize,
+    pub specular_samples: usize,
+    pub lut_size: usize,
+    pub lut_samples: usize,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            cubemap_size: 512,
+            specular_size: 128,
+            specular_mips: 6,
+            specular_samples: 64,
+            lut_size: 64,
+            lut_samples: 256,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EnvironmentMaps {
+    pub environment: Image,
+    pub irradiance: Image,
+    pub irradiance_sh: ShIrradiance,
+    pub specular: Image,
+    pub brdf_lut: Image,
+}
+
+impl EnvironmentMaps {
+    pub fn load(
+        queue: Queue,
+        path: &Path,
+        settings: &EnvironmentSettings,
+    ) -> Result<Self, LoadError> {
+        let image = load_hdr_image(path)?;
+        Self::from_image(queue, &image, settings)
+    }
+
+    pub fn from_image(
+        queue: Queue,
+        image: &HdrImage,
+        settings: &EnvironmentSettings,
+    ) -> Result<Self, LoadError> {
+        let cubemap = equirect_to_cubemap(image, settings.cubemap_size);
+        let irradiance_sh = irradiance_sh(&cubemap);
+
+        // prefilter from a smaller copy, the full size one has far more
+        // detail than the blurrier mips can use
+        let specular_source = equirect_to_cubemap(image, settings.specular_size);
+        // there's always at least the mirror-like mip 0, and at most enough
+        // to get down to 1x1
+        let max_mips =
+            std::mem::size_of::<usize>() * 8 - settings.specular_size.leading_zeros() as usize;
+        let specular_levels = prefilter_specular(
+            &specular_source,
+            settings.specular_mips.min(max_mips).max(1),
+            settings.specular_samples,
+        );
+        let lut = brdf_lut(settings.lut_size, settings.lut_samples);
+
+        let environment = upload_cubemap(queue.clone(), &[cubemap])?;
+        let specular = upload_cubemap(queue.clone(), &specular_levels)?;
+        let irradiance = upload_sh(queue.clone(), &irradiance_sh)?;
+        let brdf_lut = upload_lut(queue, &lut, settings.lut_size)?;
+
+        Ok(Self {
+            environment,
+            irradiance,
+            irradiance_sh,
+            specular,
+            brdf_lut,
+        })
+    }
+}
+
+pub fn upload_cubemap(queue: Queue, levels: &[Cubemap]) -> Result<Image, LoadError> {
+    // levels[0] is the largest, every following one is half the size of the
+    // one before it
+    let largest = levels
+        .first()
+        .ok_or_else(|| LoadError::Upload("a cubemap needs at least one level".to_string()))?;
+    let bytes: Vec<Vec<u8>> = levels.iter().map(|level| level.to_rgba32f_bytes()).collect();
+
+    upload_levels(
+        queue,
+        Dimensions::Cubemap {
+            size: largest.size as u32,
+        },
+        Format::R32G32B32A32Sfloat,
+        &bytes,
+    )
+}
+
+fn upload_sh(queue: Queue, sh: &ShIrradiance) -> Result<Image, LoadError> {
+    // a 9x1 texture, one texel per coefficient
+    let bytes: Vec<u8> = sh
+        .coefficients
+        .iter()
+        .flat_map(|c| rgba_bytes(*c))
+        .collect();
+
+    upload_levels(
+        queue,
+        dimensions_2d([9, 1]),
+        Format::R32G32B32A32Sfloat,
+        &[bytes],
+    )
+}
+
+fn upload_lut(queue: Queue, lut: &[[f32; 2]], size: usize) -> Result<Image, LoadError> {
+    let bytes: Vec<u8> = lut
+        .iter()
+        .flat_map(|texel| {
+            let mut bytes = texel[0].to_bits().to_le_bytes().to_vec();
+            bytes.extend_from_slice(&texel[1].to_bits().to_le_bytes());
+            bytes
+        })
+        .collect();
+
+    upload_levels(
+        queue,
+        dimensions_2d([size as u32, size as u32]),
+        Format::R32G32Sfloat,
+        &[bytes],
+    )
+}
+
+fn rgba_bytes(texel: [f32; 4]) -> Vec<u8> {
+    texel
+        .iter()
+        .flat_map(|c| c.to_bits().to_le_bytes().to_vec())
+        .collect()
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::mesh::AttributeError;
+use crate::texture::ContainerError;
+
+// everything that can go wrong loading meshes, textures and environment maps.
+// every variant says which file it was about so the message can be shown as-is.
+#[derive(Debug)]
+pub enum LoadError {
+    // tobj couldn't parse the OBJ or MTL file
+    Obj {
+        path: PathBuf,
+        error: tobj::LoadError,
+    },
+    MissingFile(PathBuf),
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    // the file is a format we understand, but its contents are broken
+    Undecodable {
+        path: PathBuf,
+        reason: String,
+    },
+    UnsupportedFormat {
+        path: PathBuf,
+        reason: String,
+    },
+    // a model's vertex attributes don't line up, see ConvertOptions
+    Attribute {
+        model: String,
+        error: AttributeError,
+    },
+    // the data was fine, but the device wouldn't create an image for it
+    Upload(String),
+}
+
+impl LoadError {
+    pub fn from_io(path: &Path, error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::NotFound {
+            LoadError::MissingFile(path.to_path_buf())
+        } else {
+            LoadError::Io {
+                path: path.to_path_buf(),
+                error,
+            }
+        }
+    }
+
+    pub fn from_container(path: &Path, error: ContainerError) -> Self {
+        match error {
+            ContainerError::Io(error) => Self::from_io(path, error),
+            ContainerError::UnsupportedFormat(reason) => LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                reason,
+            },
+            other => LoadError::Undecodable {
+                path: path.to_path_buf(),
+                reason: other.to_string(),
+            },
+        }
+    }
+
+    pub fn from_image(path: &Path, error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(error) => Self::from_io(path, error),
+            image::ImageError::UnsupportedError(reason) => LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                reason,
+            },
+            image::ImageError::UnsupportedColor(color) => LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                reason: format!("color type {:?}", color),
+            },
+            other => LoadError::Undecodable {
+                path: path.to_path_buf(),
+                reason: other.to_string(),
+            },
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            LoadError::Obj { path, .. }
+            | LoadError::Io { path, .. }
+            | LoadError::Undecodable { path, .. }
+            | LoadError::UnsupportedFormat { path, .. } => Some(path),
+            LoadError::MissingFile(path) => Some(path),
+            LoadError::Attribute { .. } | LoadError::Upload(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Obj { path, error } => {
+                write!(f, "couldn't parse {:?}: {:?}", path, error)
+            }
+            LoadError::MissingFile(path) => write!(f, "file not found: {:?}", path),
+            LoadError::Io { path, error } => write!(f, "couldn't read {:?}: {}", path, error),
+            LoadError::Undecodable { path, reason } => {
+                write!(f, "couldn't decode {:?}: {}", path, reason)
+            }
+            LoadError::UnsupportedFormat { path, reason } => {
+                write!(f, "{:?} uses an unsupported format: {}", path, reason)
+            }
+            LoadError::Attribute { model, error } => write!(f, "model {}: {}", model, error),
+            LoadError::Upload(reason) => write!(f, "couldn't upload to the GPU: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { error, .. } => Some(error),
+            LoadError::Attribute { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
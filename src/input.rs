@@ -0,0 +1,175 @@
+use render_engine::input::{FrameInfo, VirtualKeyCode};
+
+use nalgebra_glm::*;
+
+use std::collections::HashSet;
+
+// everything a camera needs to know about one frame of user input. cameras
+// only ever look at this, never at window events, so they can also be driven
+// by scripts or recordings by filling one in by hand:
+//
+// let input = CameraInput {
+//     movement: vec3(0.0, 0.0, 1.0),
+//     delta: 1.0 / 60.0,
+//     ..CameraInput::default()
+// };
+// camera.apply_input(&input);
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraInput {
+    // mouse movement this frame, in pixels. positive y is down.
+    pub look: [f32; 2],
+    // which way the user wants to move: x is right, y is up and z is forward.
+    // each component is -1, 0 or 1 for keyboard input.
+    pub movement: Vec3,
+    // which way to roll, positive is clockwise. -1, 0 or 1 for keyboard input.
+    pub roll: f32,
+    // scroll wheel lines this frame, positive when scrolling away from the
+    // user
+    pub zoom: f32,
+    // true while the middle mouse button is held
+    pub pan: bool,
+    // true while the "move faster" key (X) is held
+    pub sprint: bool,
+    pub modifiers: Modifiers,
+    // window size in pixels, for the aspect ratio
+    pub dimensions: [u32; 2],
+    // seconds since the last frame
+    pub delta: f32,
+}
+
+impl Default for CameraInput {
+    fn default() -> Self {
+        Self {
+            look: [0.0, 0.0],
+            movement: vec3(0.0, 0.0, 0.0),
+            roll: 0.0,
+            zoom: 0.0,
+            pan: false,
+            sprint: false,
+            modifiers: Modifiers::default(),
+            dimensions: [1, 1],
+            delta: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl From<winit::ModifiersState> for Modifiers {
+    fn from(state: winit::ModifiersState) -> Self {
+        Self {
+            shift: state.shift,
+            ctrl: state.ctrl,
+            alt: state.alt,
+            logo: state.logo,
+        }
+    }
+}
+
+// turns FrameInfos into CameraInputs. FrameInfo only has events for things
+// that changed this frame, so anything that is held down (mouse buttons, keys
+// render_engine doesn't track, modifiers) has to be remembered here.
+#[derive(Debug, Clone, Default)]
+pub struct InputTracker {
+    middle_down: bool,
+    modifiers: Modifiers,
+    keys_held: HashSet<VirtualKeyCode>,
+}
+
+impl InputTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn process(&mut self, frame_info: &FrameInfo) -> CameraInput {
+        let mut zoom = 0.0;
+
+        for ev in frame_info.all_events.iter() {
+            if let winit::Event::WindowEvent { event, .. } = ev {
+                match event {
+                    winit::WindowEvent::MouseWheel {
+                        delta, modifiers, ..
+                    } => {
+                        zoom += match delta {
+                            winit::MouseScrollDelta::LineDelta(_, y) => *y,
+                            // touchpads report pixels, about 20 of them make
+                            // up a line
+                            winit::MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.0,
+                        };
+                        self.modifiers = (*modifiers).into();
+                    }
+                    winit::WindowEvent::MouseInput {
+                        state,
+                        button,
+                        modifiers,
+                        ..
+                    } => {
+                        if *button == winit::MouseButton::Middle {
+                            self.middle_down = *state == winit::ElementState::Pressed;
+                        }
+                        self.modifiers = (*modifiers).into();
+                    }
+                    winit::WindowEvent::CursorMoved { modifiers, .. } => {
+                        self.modifiers = (*modifiers).into();
+                    }
+                    winit::WindowEvent::KeyboardInput { input, .. } => {
+                        if let Some(key) = input.virtual_keycode {
+                            if input.state == winit::ElementState::Pressed {
+                                self.keys_held.insert(key);
+                            } else {
+                                self.keys_held.remove(&key);
+                            }
+                        }
+                        self.modifiers = input.modifiers.into();
+                    }
+                    // keys released while the window is in the background
+                    // never send an event, so forget everything
+                    winit::WindowEvent::Focused(false) => {
+                        self.keys_held.clear();
+                        self.middle_down = false;
+                        self.modifiers = Modifiers::default();
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // WASD and X come from render_engine, the rest is tracked here. E and
+        // Q move up and down, the left and right arrows roll.
+        let keys = &frame_info.keys_down;
+        let axis = |positive: bool, negative: bool| -> f32 {
+            (positive as i32 - negative as i32) as f32
+        };
+        let up = axis(
+            self.is_held(VirtualKeyCode::E),
+            self.is_held(VirtualKeyCode::Q),
+        );
+        let roll = axis(
+            self.is_held(VirtualKeyCode::Right),
+            self.is_held(VirtualKeyCode::Left),
+        );
+
+        CameraInput {
+            look: frame_info.mouse_movement,
+            movement: vec3(axis(keys.d, keys.a), up, axis(keys.w, keys.s)),
+            roll,
+            zoom,
+            pan: self.middle_down,
+            sprint: keys.x,
+            modifiers: self.modifiers,
+            dimensions: frame_info.dimensions,
+            delta: frame_info.delta,
+        }
+    }
+
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        // for keys that aren't in FrameInfo's keys_down
+        self.keys_held.contains(&key)
+    }
+}
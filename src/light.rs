@@ -0,0 +1,272 @@
+use render_engine::collection::Data;
+
+use nalgebra_glm::*;
+
+// the shaders declare an array of this size, so it's a hard limit
+pub const MAX_LIGHTS: usize = 32;
+
+// a light that shines equally in every direction, like a bulb or a torch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    // distance at which the light has faded out completely
+    pub range: f32,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+// a light so far away that all of its rays are parallel, like the sun
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    // points towards the light, not away from it
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+// a light that shines in a cone, like a torch or a stage light. angles are
+// measured from the direction to the edge of the cone, in radians: inside
+// inner_angle the light is at full strength, between inner_angle and
+// outer_angle it fades out smoothly and outside outer_angle there's no light
+// at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    // distance at which the light has faded out completely
+    pub range: f32,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Point(PointLight),
+    Directional(DirectionalLight),
+    Spot(SpotLight),
+}
+
+// any kind of light as the shaders see it, see the Light struct in
+// shaders/pretty/all_frag.glsl
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightData {
+    // xyz is the position, w is the kind: 0 for point, 1 for directional and
+    // 2 for spot lights
+    pub position: [f32; 4],
+    // xyz is where directional and spot lights point, w is the range
+    pub direction: [f32; 4],
+    // rgb is color * intensity
+    pub color: [f32; 4],
+    // cosine of the inner angle, cosine of the outer angle, index of the
+    // light's shadow map or -1 if it has none, unused
+    pub cone: [f32; 4],
+}
+
+// up to MAX_LIGHTS lights of any kind, so a scene can have more than one.
+// shaders loop over the first count.x lights.
+#[derive(Debug, Clone, Default)]
+pub struct LightList {
+    lights: Vec<(Light, Option<usize>)>,
+}
+
+#[derive(Clone, Copy)]
+pub struct LightListData {
+    // x is the number of lights, the rest is unused
+    pub count: [f32; 4],
+    pub lights: [LightData; MAX_LIGHTS],
+}
+
+impl Data for LightListData {}
+
+impl PointLight {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            range: 50.0,
+            color: vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn with_color(mut self, color: Vec3, intensity: f32) -> Self {
+        self.color = color;
+        self.intensity = intensity;
+        self
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3) -> Self {
+        Self {
+            direction: normalize(&direction),
+            color: vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn with_color(mut self, color: Vec3, intensity: f32) -> Self {
+        self.color = color;
+        self.intensity = intensity;
+        self
+    }
+}
+
+impl SpotLight {
+    pub fn new(position: Vec3, direction: Vec3) -> Self {
+        Self {
+            position,
+            direction: normalize(&direction),
+            inner_angle: 0.3,
+            outer_angle: 0.5,
+            range: 50.0,
+            color: vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn pointing_at(position: Vec3, target: Vec3) -> Self {
+        Self::new(position, target - position)
+    }
+
+    pub fn with_cone(mut self, inner_angle: f32, outer_angle: f32) -> Self {
+        // the fade needs inner < outer, and a cone of 90 degrees or more
+        // can't be rendered with a single perspective shadow map
+        self.outer_angle = outer_angle
+            .max(0.001)
+            .min(std::f32::consts::FRAC_PI_2 - 0.01);
+        self.inner_angle = inner_angle.max(0.0).min(self.outer_angle);
+        self
+    }
+
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn with_color(mut self, color: Vec3, intensity: f32) -> Self {
+        self.color = color;
+        self.intensity = intensity;
+        self
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Light::Directional(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+impl Light {
+    // directional lights are infinitely far away, so they don't have one
+    pub fn position(&self) -> Option<Vec3> {
+        match self {
+            Light::Point(light) => Some(light.position),
+            Light::Directional(_) => None,
+            Light::Spot(light) => Some(light.position),
+        }
+    }
+
+    pub fn get_data(&self, shadow: Option<usize>) -> LightData {
+        let shadow = shadow.map(|idx| idx as f32).unwrap_or(-1.0);
+
+        let (kind, position, direction, range, color, cone) = match *self {
+            Light::Point(light) => (
+                0.0,
+                light.position,
+                vec3(0.0, 0.0, 0.0),
+                light.range,
+                light.color * light.intensity,
+                [1.0, 1.0],
+            ),
+            Light::Directional(light) => (
+                1.0,
+                vec3(0.0, 0.0, 0.0),
+                normalize(&light.direction),
+                0.0,
+                light.color * light.intensity,
+                [1.0, 1.0],
+            ),
+            Light::Spot(light) => (
+                2.0,
+                light.position,
+                normalize(&light.direction),
+                light.range,
+                light.color * light.intensity,
+                [light.inner_angle.cos(), light.outer_angle.cos()],
+            ),
+        };
+
+        LightData {
+            position: [position.x, position.y, position.z, kind],
+            direction: [direction.x, direction.y, direction.z, range],
+            color: [color.x, color.y, color.z, 0.0],
+            cone: [cone[0], cone[1], shadow, 0.0],
+        }
+    }
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<L: Into<Light>>(&mut self, light: L) {
+        self.lights.push((light.into(), None));
+    }
+
+    pub fn push_shadowed<L: Into<Light>>(&mut self, light: L, shadow_index: usize) {
+        // shadow_index says which shadow map the shaders should use for the
+        // light, what that means depends on the shader
+        self.lights.push((light.into(), Some(shadow_index)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn lights(&self) -> impl Iterator<Item = &Light> {
+        self.lights.iter().map(|(light, _)| light)
+    }
+
+    pub fn get_data(&self) -> LightListData {
+        // lights past MAX_LIGHTS are left out
+        let empty = LightData {
+            position: [0.0; 4],
+            direction: [0.0; 4],
+            color: [0.0; 4],
+            cone: [1.0, 1.0, -1.0, 0.0],
+        };
+        let mut lights = [empty; MAX_LIGHTS];
+        for (slot, (light, shadow)) in lights.iter_mut().zip(self.lights.iter()) {
+            *slot = light.get_data(*shadow);
+        }
+
+        LightListData {
+            count: [self.lights.len().min(MAX_LIGHTS) as f32, 0.0, 0.0, 0.0],
+            lights,
+        }
+    }
+}
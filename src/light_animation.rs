@@ -0,0 +1,575 @@
+use render_engine::input::{FrameInfo, VirtualKeyCode};
+
+use nalgebra_glm::*;
+
+use crate::camera_path::{catmull_rom, Easing};
+use crate::light::{Light, LightList};
+
+// a light that moves and changes brightness over time. nothing here looks at
+// the clock: sample takes the time, so animations can be paused, scrubbed and
+// replayed exactly, and two runs sampled at the same times light the scene the
+// same way.
+//
+// let torch = AnimatedLight::new(PointLight::new(vec3(0.0, 5.0, 0.0)))
+//     .with_intensity(IntensityCurve::flicker(0.3, 8.0, 1));
+// let lights = sample_lights(&[torch], clock.time);
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimatedLight {
+    // the light at rest. motion replaces its position and intensity scales its
+    // intensity, everything else is left alone.
+    pub light: Light,
+    pub motion: Motion,
+    pub intensity: IntensityCurve,
+    // spot lights keep pointing at this while they move
+    pub target: Option<Vec3>,
+    // which shadow map the light uses, see LightList::push_shadowed
+    pub shadow: Option<usize>,
+}
+
+// where a light is at a given time. directional lights don't have a position,
+// so for them the position is taken as the direction towards the light, as if
+// the light were a sun circling the origin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Motion {
+    // stays wherever the light already is
+    Static,
+    // circles center in the xz plane, height above it. a negative period
+    // circles the other way.
+    Orbit {
+        center: Vec3,
+        radius: f32,
+        height: f32,
+        // seconds per lap
+        period: f32,
+        // angle at time 0, in radians
+        phase: f32,
+    },
+    // moves from one point to another, then starts over or heads back
+    Linear {
+        from: Vec3,
+        to: Vec3,
+        // seconds from one end to the other
+        duration: f32,
+        ping_pong: bool,
+        easing: Easing,
+    },
+    // passes through every keyframe at its time along a centripetal
+    // Catmull-Rom spline, like a CameraPath
+    Spline {
+        keyframes: Vec<PositionKey>,
+        looping: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionKey {
+    // in seconds
+    pub time: f32,
+    pub position: Vec3,
+}
+
+// what the light's intensity is multiplied by over time
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntensityCurve {
+    Constant,
+    // a sine wave between min and max
+    Pulse {
+        min: f32,
+        max: f32,
+        period: f32,
+    },
+    // random-looking dips like a flame. it's smooth noise seeded by seed, so
+    // it's the same every run: lights with different seeds flicker
+    // differently, with the same seed identically.
+    Flicker {
+        amount: f32,
+        speed: f32,
+        seed: u32,
+    },
+    // linear between (time, multiplier) keys. before the first key and after
+    // the last one the multiplier is held, unless the curve loops.
+    Keyframed {
+        keys: Vec<(f32, f32)>,
+        looping: bool,
+    },
+}
+
+// the time animations are sampled at. advances with the frames unless it's
+// paused and can be moved by hand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationClock {
+    pub time: f32,
+    // 1 is real time, 0.5 half speed and so on
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl AnimatedLight {
+    pub fn new<L: Into<Light>>(light: L) -> Self {
+        Self {
+            light: light.into(),
+            motion: Motion::Static,
+            intensity: IntensityCurve::Constant,
+            target: None,
+            shadow: None,
+        }
+    }
+
+    pub fn with_motion(mut self, motion: Motion) -> Self {
+        self.motion = motion;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: IntensityCurve) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn aiming_at(mut self, target: Vec3) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn with_shadow(mut self, shadow_index: usize) -> Self {
+        self.shadow = Some(shadow_index);
+        self
+    }
+
+    pub fn sample(&self, time: f32) -> Light {
+        let position = self.motion.sample(time);
+        let multiplier = self.intensity.sample(time);
+
+        match self.light {
+            Light::Point(mut light) => {
+                light.position = position.unwrap_or(light.position);
+                light.intensity *= multiplier;
+                Light::Point(light)
+            }
+            Light::Directional(mut light) => {
+                light.direction = position
+                    .map(|position| normalize(&position))
+                    .unwrap_or(light.direction);
+                light.intensity *= multiplier;
+                Light::Directional(light)
+            }
+            Light::Spot(mut light) => {
+                light.position = position.unwrap_or(light.position);
+                if let Some(target) = self.target {
+                    // aiming at itself would give a NaN direction
+                    if distance(&target, &light.position) > 1e-4 {
+                        light.direction = normalize(&(target - light.position));
+                    }
+                }
+                light.intensity *= multiplier;
+                Light::Spot(light)
+            }
+        }
+    }
+}
+
+pub fn sample_lights(lights: &[AnimatedLight], time: f32) -> LightList {
+    let mut list = LightList::new();
+    for light in lights {
+        match light.shadow {
+            Some(shadow) => list.push_shadowed(light.sample(time), shadow),
+            None => list.push(light.sample(time)),
+        }
+    }
+
+    list
+}
+
+impl Motion {
+    pub fn orbit(center: Vec3, radius: f32, height: f32, period: f32) -> Self {
+        Motion::Orbit {
+            center,
+            radius,
+            height,
+            period,
+            phase: 0.0,
+        }
+    }
+
+    pub fn linear(from: Vec3, to: Vec3, duration: f32) -> Self {
+        Motion::Linear {
+            from,
+            to,
+            duration,
+            ping_pong: true,
+            easing: Easing::Smooth,
+        }
+    }
+
+    pub fn spline(mut keyframes: Vec<PositionKey>, looping: bool) -> Self {
+        // sample relies on the keyframes being in order
+        keyframes.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        Motion::Spline { keyframes, looping }
+    }
+
+    // None for Static, the light keeps its own position then
+    pub fn sample(&self, time: f32) -> Option<Vec3> {
+        match self {
+            Motion::Static => None,
+            Motion::Orbit {
+                center,
+                radius,
+                height,
+                period,
+                phase,
+            } => {
+                let angle = if *period != 0.0 {
+                    phase + time / period * std::f32::consts::PI * 2.0
+                } else {
+                    *phase
+                };
+
+                Some(center + vec3(angle.cos() * radius, *height, angle.sin() * radius))
+            }
+            Motion::Linear {
+                from,
+                to,
+                duration,
+                ping_pong,
+                easing,
+            } => {
+                if *duration <= 0.0 {
+                    return Some(*to);
+                }
+
+                // u goes 0..1 and then either jumps back or returns to 0
+                let u = if *ping_pong {
+                    let u = (time / duration).rem_euclid(2.0);
+                    if u > 1.0 {
+                        2.0 - u
+                    } else {
+                        u
+                    }
+                } else {
+                    (time / duration).rem_euclid(1.0)
+                };
+                let u = match easing {
+                    Easing::Linear => u,
+                    Easing::Smooth => u * u * (3.0 - 2.0 * u),
+                };
+
+                Some(from + (to - from) * u)
+            }
+            Motion::Spline { keyframes, looping } => sample_spline(keyframes, *looping, time),
+        }
+    }
+}
+
+fn sample_spline(keys: &[PositionKey], looping: bool, time: f32) -> Option<Vec3> {
+    // the same as CameraPath::sample, minus the view direction
+    match keys.len() {
+        0 => return None,
+        1 => return Some(keys[0].position),
+        _ => {}
+    }
+
+    let start = keys[0].time;
+    let end = keys[keys.len() - 1].time;
+    let time = if looping && end > start {
+        start + (time - start).rem_euclid(end - start)
+    } else {
+        time.max(start).min(end)
+    };
+
+    let idx = keys
+        .iter()
+        .rposition(|key| key.time <= time)
+        .unwrap_or(0)
+        .min(keys.len() - 2);
+
+    let (key1, key2) = (&keys[idx], &keys[idx + 1]);
+    let segment_length = key2.time - key1.time;
+    let u = if segment_length > 0.0 {
+        ((time - key1.time) / segment_length).max(0.0).min(1.0)
+    } else {
+        1.0
+    };
+
+    let (p1, p2) = (key1.position, key2.position);
+    let p0 = if idx > 0 {
+        keys[idx - 1].position
+    } else {
+        p1 * 2.0 - p2
+    };
+    let p3 = if idx + 2 < keys.len() {
+        keys[idx + 2].position
+    } else {
+        p2 * 2.0 - p1
+    };
+
+    Some(catmull_rom(p0, p1, p2, p3, u, 0.5))
+}
+
+impl IntensityCurve {
+    pub fn pulse(min: f32, max: f32, period: f32) -> Self {
+        IntensityCurve::Pulse { min, max, period }
+    }
+
+    pub fn flicker(amount: f32, speed: f32, seed: u32) -> Self {
+        IntensityCurve::Flicker {
+            amount,
+            speed,
+            seed,
+        }
+    }
+
+    pub fn sample(&self, time: f32) -> f32 {
+        match self {
+            IntensityCurve::Constant => 1.0,
+            IntensityCurve::Pulse { min, max, period } => {
+                if *period == 0.0 {
+                    return *max;
+                }
+                let wave = (time / period * std::f32::consts::PI * 2.0).sin() * 0.5 + 0.5;
+
+                min + (max - min) * wave
+            }
+            IntensityCurve::Flicker {
+                amount,
+                speed,
+                seed,
+            } => {
+                // two octaves, one slow and one fast, so it doesn't look like
+                // a single wobble
+                let x = time * speed;
+                let noise =
+                    value_noise(x, *seed) * 0.65 + value_noise(x * 2.7 + 17.0, *seed) * 0.35;
+
+                1.0 - amount * noise
+            }
+            IntensityCurve::Keyframed { keys, looping } => sample_keys(keys, *looping, time),
+        }
+    }
+}
+
+fn sample_keys(keys: &[(f32, f32)], looping: bool, time: f32) -> f32 {
+    // keys are (time, value) and have to be in order
+    match keys.len() {
+        0 => return 1.0,
+        1 => return keys[0].1,
+        _ => {}
+    }
+
+    let (start, end) = (keys[0].0, keys[keys.len() - 1].0);
+    let time = if looping && end > start {
+        start + (time - start).rem_euclid(end - start)
+    } else {
+        time.max(start).min(end)
+    };
+
+    let idx = keys
+        .iter()
+        .rposition(|key| key.0 <= time)
+        .unwrap_or(0)
+        .min(keys.len() - 2);
+    let ((t1, v1), (t2, v2)) = (keys[idx], keys[idx + 1]);
+    let u = if t2 > t1 {
+        ((time - t1) / (t2 - t1)).max(0.0).min(1.0)
+    } else {
+        1.0
+    };
+
+    v1 + (v2 - v1) * u
+}
+
+fn value_noise(x: f32, seed: u32) -> f32 {
+    // random values at whole numbers, smoothly blended in between. 0..1
+    let cell = x.floor();
+    let u = x - cell;
+    let u = u * u * (3.0 - 2.0 * u);
+    let a = hash(cell as i32, seed);
+    let b = hash(cell as i32 + 1, seed);
+
+    a + (b - a) * u
+}
+
+fn hash(n: i32, seed: u32) -> f32 {
+    // any integer to 0..1, the same every time
+    let mut h = (n as u32).wrapping_mul(0x9e37_79b9) ^ seed.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+
+    h as f32 / std::u32::MAX as f32
+}
+
+impl Default for AnimationClock {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl AnimationClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn update(&mut self, frame_info: &FrameInfo) {
+        self.handle_keys(frame_info);
+        self.advance(frame_info.delta);
+    }
+
+    // L pauses and resumes, [ and ] scrub a second back or forward
+    pub fn handle_keys(&mut self, frame_info: &FrameInfo) {
+        if frame_info.keydowns.contains(&VirtualKeyCode::L) {
+            self.paused = !self.paused;
+        }
+        if frame_info.keydowns.contains(&VirtualKeyCode::LBracket) {
+            self.scrub(-1.0);
+        }
+        if frame_info.keydowns.contains(&VirtualKeyCode::RBracket) {
+            self.scrub(1.0);
+        }
+    }
+
+    // for driving the clock without a window, e.g. from a recording's deltas
+    pub fn advance(&mut self, delta: f32) {
+        if !self.paused {
+            self.time += delta * self.speed;
+        }
+    }
+
+    // works while paused too
+    pub fn scrub(&mut self, seconds: f32) {
+        self.time = (self.time + seconds).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::PointLight;
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            distance(&actual, &expected) < 1e-4,
+            "got {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn orbit() {
+        let motion = Motion::orbit(vec3(1.0, 2.0, 3.0), 2.0, 5.0, 4.0);
+
+        assert_near(motion.sample(0.0).unwrap(), vec3(3.0, 7.0, 3.0));
+        assert_near(motion.sample(1.0).unwrap(), vec3(1.0, 7.0, 5.0));
+        assert_near(motion.sample(2.0).unwrap(), vec3(-1.0, 7.0, 3.0));
+        assert_near(motion.sample(4.0).unwrap(), vec3(3.0, 7.0, 3.0));
+        assert_near(motion.sample(-1.0).unwrap(), vec3(1.0, 7.0, 1.0));
+    }
+
+    #[test]
+    fn linear() {
+        let from = vec3(0.0, 0.0, 0.0);
+        let to = vec3(10.0, 0.0, 0.0);
+        let ping_pong = Motion::Linear {
+            from,
+            to,
+            duration: 2.0,
+            ping_pong: true,
+            easing: Easing::Linear,
+        };
+        let wrapping = Motion::Linear {
+            from,
+            to,
+            duration: 2.0,
+            ping_pong: false,
+            easing: Easing::Linear,
+        };
+
+        assert_near(ping_pong.sample(1.0).unwrap(), vec3(5.0, 0.0, 0.0));
+        assert_near(ping_pong.sample(2.0).unwrap(), to);
+        assert_near(ping_pong.sample(3.5).unwrap(), vec3(2.5, 0.0, 0.0));
+        assert_near(ping_pong.sample(4.0).unwrap(), from);
+        assert_near(wrapping.sample(2.5).unwrap(), vec3(2.5, 0.0, 0.0));
+
+        // smoothstep: a quarter of the way in time is 0.15625 of the way
+        let smooth = Motion::linear(from, to, 2.0);
+        assert_near(smooth.sample(0.5).unwrap(), vec3(1.5625, 0.0, 0.0));
+    }
+
+    #[test]
+    fn looping_spline() {
+        let key = |time, x, z| PositionKey {
+            time,
+            position: vec3(x, 0.0, z),
+        };
+        // out of order on purpose, spline sorts them
+        let motion = Motion::spline(
+            vec![
+                key(2.0, 10.0, 10.0),
+                key(0.0, 0.0, 0.0),
+                key(1.0, 10.0, 0.0),
+            ],
+            true,
+        );
+
+        assert_near(motion.sample(0.0).unwrap(), vec3(0.0, 0.0, 0.0));
+        assert_near(motion.sample(1.0).unwrap(), vec3(10.0, 0.0, 0.0));
+        // one loop lasts 2 seconds
+        assert_near(motion.sample(3.0).unwrap(), vec3(10.0, 0.0, 0.0));
+        assert_near(motion.sample(-1.0).unwrap(), vec3(10.0, 0.0, 0.0));
+        assert_near(motion.sample(2.5).unwrap(), motion.sample(0.5).unwrap());
+        assert_near(motion.sample(101.25).unwrap(), motion.sample(1.25).unwrap());
+    }
+
+    #[test]
+    fn flicker_is_seeded() {
+        let a = IntensityCurve::flicker(0.3, 8.0, 1);
+        let b = IntensityCurve::flicker(0.3, 8.0, 1);
+        let other_seed = IntensityCurve::flicker(0.3, 8.0, 2);
+
+        let times: Vec<f32> = (0..200).map(|i| i as f32 * 0.037).collect();
+        for &time in times.iter() {
+            let value = a.sample(time);
+            assert_eq!(value.to_bits(), b.sample(time).to_bits());
+            assert!((0.7..=1.0).contains(&value), "{} at {}", value, time);
+        }
+        assert!(times
+            .iter()
+            .any(|&time| a.sample(time) != other_seed.sample(time)));
+
+        // and lights using it come out the same too
+        let torch = AnimatedLight::new(PointLight::new(vec3(0.0, 5.0, 0.0))).with_intensity(a);
+        assert_eq!(torch.sample(12.5), torch.clone().sample(12.5));
+    }
+
+    #[test]
+    fn clock() {
+        let mut clock = AnimationClock::new().with_speed(0.5);
+        clock.advance(2.0);
+        assert_eq!(clock.time, 1.0);
+
+        clock.paused = true;
+        clock.advance(2.0);
+        assert_eq!(clock.time, 1.0);
+
+        // scrubbing works while paused and stops at 0
+        clock.scrub(1.5);
+        assert_eq!(clock.time, 2.5);
+        clock.scrub(-10.0);
+        assert_eq!(clock.time, 0.0);
+    }
+}
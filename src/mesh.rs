@@ -5,140 +5,373 @@ Object: mesh + other stuff.
  */
 
 use render_engine::mesh::{Mesh, PrimitiveTopology, Vertex};
-use render_engine::utils::load_texture;
 use render_engine::{Format, Queue, Image, RenderPass};
 use render_engine::object::{ObjectPrototype, Object};
 
+use crate::error::LoadError;
 use crate::relative_path;
+use crate::report::{LoadReport, MaterialReport, TextureReport, TextureSource};
+use crate::texture::load_texture_file_measured;
 
 use nalgebra_glm::*;
 
 use std::path::{Path, PathBuf};
 
-pub use tobj::load_obj;
+pub fn load_obj(path: &Path) -> Result<(Vec<tobj::Model>, Vec<tobj::Material>), LoadError> {
+    // tobj's own errors don't say which file they were about and can't tell a
+    // missing file apart from a broken one, so check for that first
+    if !path.exists() {
+        return Err(LoadError::MissingFile(path.to_path_buf()));
+    }
+
+    tobj::load_obj(path).map_err(|error| LoadError::Obj {
+        path: path.to_path_buf(),
+        error,
+    })
+}
 
-pub fn convert_meshes(models: &[tobj::Model]) -> Vec<Mesh<VPosTexNorm>> {
+pub fn convert_meshes(models: &[tobj::Model]) -> Result<Vec<Mesh<VPosTexNorm>>, LoadError> {
     // converts all provided into meshes of type VPosTexNorm, which includes all
     // information commonly incldued in obj files: positions, texture
-    // coordinates and normals.
-    models
-        .iter()
-        .map(|model| convert_mesh(&model.mesh))
-        .collect()
+    // coordinates and normals. missing texture coordinates are replaced with
+    // a dummy value and missing normals are an error, use convert_meshes_with
+    // to change that.
+    convert_meshes_with(models, &ConvertOptions::default())
 }
 
 pub fn load_textures(
     queue: Queue,
     root_path: &Path,
     materials: &[tobj::Material],
-) -> Vec<(Image, Image, Image)> {
+) -> Result<Vec<(Image, Image, Image)>, LoadError> {
     // loads all textures for all materials provided by returning 3 images for
     // each material: a diffuse texture, a specular texture, and a normal
-    // texture, in that order
+    // texture, in that order. materials without a texture or pointing to one
+    // that doesn't exist get a placeholder, but a texture that exists and
+    // can't be decoded is an error.
+    load_textures_reported(queue, root_path, materials, &mut LoadReport::default())
+}
+
+pub fn load_textures_reported(
+    queue: Queue,
+    root_path: &Path,
+    materials: &[tobj::Material],
+    report: &mut LoadReport,
+) -> Result<Vec<(Image, Image, Image)>, LoadError> {
+    // same as load_textures, but records where every texture came from, how
+    // long it took and how big it is
     materials
         .iter()
         .map(|mat| {
-            // diffuse
-            let diff_path = {
-                let maybe_path = root_path.join(Path::new(&mat.diffuse_texture));
-
-                // if the diffuse texture path is empty or the file doesn't
-                // exist, use a placeholder
-                if mat.diffuse_texture == "" {
-                    println!("{} has no diffuse texture", mat.name);
-                    relative_path("textures/missing.png")
-                } else if !maybe_path.exists() {
-                    println!(
-                        "{} diffuse texture does not exist: {:?}",
-                        mat.name, maybe_path
-                    );
-                    relative_path("textures/missing.png")
-                } else {
-                    maybe_path
-                }
-            };
-
-            // specular
-            let spec_path = {
-                let maybe_path = root_path.join(Path::new(&mat.specular_texture));
-
-                if mat.specular_texture == "" {
-                    println!("{} has no specular texture", mat.name);
-                    relative_path("textures/missing-spec.png")
-                } else if !maybe_path.exists() {
-                    println!(
-                        "{} specular texture does not exist: {:?}",
-                        mat.name, maybe_path
-                    );
-                    relative_path("textures/missing-spec.png")
-                } else {
-                    maybe_path
-                }
-            };
-
-            // normal
-            let normal_path = {
-                let maybe_path = root_path.join(Path::new(&mat.normal_texture));
-
-                if mat.normal_texture == "" {
-                    println!("{} has no normal texture", mat.name);
-                    relative_path("textures/missing-normal.png")
-                } else if !maybe_path.exists() {
-                    println!(
-                        "{} normal texture does not exist: {:?}",
-                        mat.name, maybe_path
-                    );
-                    relative_path("textures/missing-normal.png")
-                } else {
-                    maybe_path
-                }
-            };
+            // KTX2 and DDS files are uploaded still compressed, everything else
+            // goes through the image crate
+            let (diff_tex, diffuse) = load_texture_slot(
+                queue.clone(),
+                root_path,
+                &mat.diffuse_texture,
+                "textures/missing.png",
+                Format::R8G8B8A8Srgb,
+            )?;
+            let (spec_tex, specular) = load_texture_slot(
+                queue.clone(),
+                root_path,
+                &mat.specular_texture,
+                "textures/missing-spec.png",
+                Format::R8G8B8A8Unorm,
+            )?;
+            let (norm_tex, normal) = load_texture_slot(
+                queue.clone(),
+                root_path,
+                &mat.normal_texture,
+                "textures/missing-normal.png",
+                Format::R8G8B8A8Unorm,
+            )?;
+
+            report.materials.push(MaterialReport {
+                name: mat.name.clone(),
+                diffuse,
+                specular,
+                normal,
+            });
+
+            Ok((diff_tex, spec_tex, norm_tex))
+        })
+        .collect()
+}
 
-            let diff_tex = load_texture(queue.clone(), &diff_path, Format::R8G8B8A8Srgb);
-            let spec_tex = load_texture(queue.clone(), &spec_path, Format::R8G8B8A8Unorm);
-            let norm_tex = load_texture(queue.clone(), &normal_path, Format::R8G8B8A8Unorm);
+fn load_texture_slot(
+    queue: Queue,
+    root_path: &Path,
+    texture: &str,
+    placeholder: &str,
+    format: Format,
+) -> Result<(Image, TextureReport), LoadError> {
+    // if the texture path is empty or the file doesn't exist, use a
+    // placeholder
+    let maybe_path = root_path.join(Path::new(texture));
+    let (path, source) = if texture == "" {
+        (relative_path(placeholder), TextureSource::Fallback)
+    } else if !maybe_path.exists() {
+        (relative_path(placeholder), TextureSource::Missing)
+    } else {
+        (maybe_path, TextureSource::File)
+    };
+
+    let start_time = std::time::Instant::now();
+    let (image, bytes) = load_texture_file_measured(queue, &path, format)?;
+    let decode_ms = start_time.elapsed().as_micros() as f32 / 1000.0;
+
+    Ok((
+        image,
+        TextureReport {
+            source,
+            path,
+            decode_ms,
+            bytes,
+        },
+    ))
+}
 
-            (diff_tex, spec_tex, norm_tex)
+pub fn convert_meshes_with(
+    models: &[tobj::Model],
+    options: &ConvertOptions,
+) -> Result<Vec<Mesh<VPosTexNorm>>, LoadError> {
+    // like convert_meshes, but with control over what happens when normals or
+    // texture coordinates are missing
+    models
+        .iter()
+        .map(|model| {
+            convert_mesh_with(&model.mesh, options).map_err(|error| LoadError::Attribute {
+                model: model.name.clone(),
+                error,
+            })
         })
         .collect()
 }
 
+#[deprecated(note = "panics on missing normals, use convert_mesh_with or convert_meshes")]
 pub fn convert_mesh(mesh: &tobj::Mesh) -> Mesh<VPosTexNorm> {
-    // converts a tobj mesh to one of vertices render-engine will be able to use
-    let mut vertices: Vec<VPosTexNorm> = vec![];
-
-    for i in 0..mesh.positions.len() / 3 {
-        let pos = [
-            mesh.positions[i * 3],
-            mesh.positions[i * 3 + 1],
-            mesh.positions[i * 3 + 2],
-        ];
-        let normal = [
-            mesh.normals[i * 3],
-            mesh.normals[i * 3 + 1],
-            mesh.normals[i * 3 + 2],
-        ];
-        // if no texture coordinates are found, use a dummy value
-        // TODO: let the user specify how lenient they want to be with this
-        let tex_coord = if mesh.texcoords.len() <= i * 2 + 1 {
-            [0.0, 0.0]
-        } else {
-            [mesh.texcoords[i * 2], mesh.texcoords[i * 2 + 1] * -1.0]
+    // converts a tobj mesh to one of vertices render-engine will be able to
+    // use. missing texture coordinates are replaced with a dummy value and
+    // missing normals are a panic, use convert_mesh_with to change that.
+    convert_mesh_with(mesh, &ConvertOptions::default())
+        .unwrap_or_else(|e| panic!("Couldn't convert mesh: {}", e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributePolicy {
+    // missing data is an error
+    Strict,
+    // missing data is replaced with a placeholder value
+    Lenient,
+    // missing data is computed from the geometry
+    Generate,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConvertOptions {
+    pub normals: AttributePolicy,
+    pub tex_coords: AttributePolicy,
+    // texture coordinates generated by box projection repeat every
+    // 1 / box_projection_scale units
+    pub box_projection_scale: f32,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            normals: AttributePolicy::Strict,
+            tex_coords: AttributePolicy::Lenient,
+            box_projection_scale: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attribute {
+    Normals,
+    TexCoords,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttributeError {
+    pub attribute: Attribute,
+    // both are in values per vertex, so 3 floats for a normal count as 1
+    pub expected: usize,
+    pub found: usize,
+}
+
+impl std::fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self.attribute {
+            Attribute::Normals => "normals",
+            Attribute::TexCoords => "texture coordinates",
         };
+        write!(
+            f,
+            "mesh has {} vertices but only {} {}",
+            self.expected, self.found, name
+        )
+    }
+}
 
-        let vertex = VPosTexNorm {
-            position: pos,
+impl std::error::Error for AttributeError {}
+
+pub fn convert_mesh_with(
+    mesh: &tobj::Mesh,
+    options: &ConvertOptions,
+) -> Result<Mesh<VPosTexNorm>, AttributeError> {
+    let vertex_count = mesh.positions.len() / 3;
+    let positions: Vec<[f32; 3]> = (0..vertex_count)
+        .map(|i| {
+            [
+                mesh.positions[i * 3],
+                mesh.positions[i * 3 + 1],
+                mesh.positions[i * 3 + 2],
+            ]
+        })
+        .collect();
+
+    // normals
+    let normal_count = mesh.normals.len() / 3;
+    let normals: Vec<[f32; 3]> = if normal_count >= vertex_count {
+        (0..vertex_count)
+            .map(|i| {
+                [
+                    mesh.normals[i * 3],
+                    mesh.normals[i * 3 + 1],
+                    mesh.normals[i * 3 + 2],
+                ]
+            })
+            .collect()
+    } else {
+        match options.normals {
+            AttributePolicy::Strict => {
+                return Err(AttributeError {
+                    attribute: Attribute::Normals,
+                    expected: vertex_count,
+                    found: normal_count,
+                })
+            }
+            // keep whatever normals there are and point the rest up
+            AttributePolicy::Lenient => (0..vertex_count)
+                .map(|i| {
+                    if i < normal_count {
+                        [
+                            mesh.normals[i * 3],
+                            mesh.normals[i * 3 + 1],
+                            mesh.normals[i * 3 + 2],
+                        ]
+                    } else {
+                        [0.0, 1.0, 0.0]
+                    }
+                })
+                .collect(),
+            AttributePolicy::Generate => generate_normals(&positions, &mesh.indices),
+        }
+    };
+
+    // texture coordinates. obj files have v pointing up, vulkan has it
+    // pointing down, so v gets flipped
+    let tex_coord_count = mesh.texcoords.len() / 2;
+    let tex_coords: Vec<[f32; 2]> = if tex_coord_count >= vertex_count {
+        (0..vertex_count)
+            .map(|i| [mesh.texcoords[i * 2], mesh.texcoords[i * 2 + 1] * -1.0])
+            .collect()
+    } else {
+        match options.tex_coords {
+            AttributePolicy::Strict => {
+                return Err(AttributeError {
+                    attribute: Attribute::TexCoords,
+                    expected: vertex_count,
+                    found: tex_coord_count,
+                })
+            }
+            AttributePolicy::Lenient => (0..vertex_count)
+                .map(|i| {
+                    if i < tex_coord_count {
+                        [mesh.texcoords[i * 2], mesh.texcoords[i * 2 + 1] * -1.0]
+                    } else {
+                        [0.0, 0.0]
+                    }
+                })
+                .collect(),
+            AttributePolicy::Generate => {
+                box_project_tex_coords(&positions, &normals, options.box_projection_scale)
+            }
+        }
+    };
+
+    let vertices = positions
+        .into_iter()
+        .zip(tex_coords)
+        .zip(normals)
+        .map(|((position, tex_coord), normal)| VPosTexNorm {
+            position,
             tex_coord,
             normal,
-        };
-
-        vertices.push(vertex);
-    }
+        })
+        .collect();
 
-    Mesh {
+    Ok(Mesh {
         vertices,
         indices: mesh.indices.clone(),
+    })
+}
+
+fn generate_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    // smooth normals: every vertex gets the sum of the normals of the faces
+    // using it. the cross product isn't normalized first, so bigger faces
+    // count for more.
+    let mut normals = vec![vec3(0.0, 0.0, 0.0); positions.len()];
+
+    for face in indices.chunks(3).filter(|face| face.len() == 3) {
+        let v1 = make_vec3(&positions[face[0] as usize]);
+        let v2 = make_vec3(&positions[face[1] as usize]);
+        let v3 = make_vec3(&positions[face[2] as usize]);
+        let face_normal = (v2 - v1).cross(&(v3 - v1));
+
+        for idx in face {
+            normals[*idx as usize] += face_normal;
+        }
     }
+
+    normals
+        .iter()
+        .map(|n| {
+            // vertices not used by any face still need something valid
+            if length(n) > 0.0 {
+                normalize(n).into()
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        })
+        .collect()
+}
+
+fn box_project_tex_coords(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    scale: f32,
+) -> Vec<[f32; 2]> {
+    // projects each vertex onto the plane facing the biggest component of its
+    // normal, like wrapping the mesh in a box
+    positions
+        .iter()
+        .zip(normals.iter())
+        .map(|(p, n)| {
+            let (x, y, z) = (n[0].abs(), n[1].abs(), n[2].abs());
+            let uv = if x >= y && x >= z {
+                [p[2], p[1]]
+            } else if y >= z {
+                [p[0], p[2]]
+            } else {
+                [p[0], p[1]]
+            };
+
+            [uv[0] * scale, -uv[1] * scale]
+        })
+        .collect()
 }
 
 pub fn add_tangents_multi(meshes: &[Mesh<VPosTexNorm>]) -> Vec<Mesh<VPosTexNormTan>> {
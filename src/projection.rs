@@ -0,0 +1,370 @@
+// projection matrices that output vulkan clip space directly: x right, y down,
+// and depth from 0 to 1. glm's matrices are made for OpenGL (y up, depth from
+// -1 to 1), which is why the cameras used to flip y by hand and wasted half of
+// the depth range.
+//
+// view space is right-handed with the camera looking down -z, same as what
+// look_at produces.
+
+use vulkano::pipeline::depth_stencil::Compare;
+
+use nalgebra_glm::*;
+
+// how a camera maps view space to clip space. all distances are in view space
+// units and positive, so near: 0.1 means 0.1 units in front of the camera.
+// far can be std::f32::INFINITY for perspective and off-centre projections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // fov is the vertical field of view in radians, the horizontal one
+    // follows from the aspect ratio of the window
+    Perspective { fov: f32, near: f32, far: f32 },
+    // height is how many units fit vertically on screen, the width again
+    // follows from the aspect ratio. far must be finite.
+    Orthographic { height: f32, near: f32, far: f32 },
+    // an asymmetric perspective frustum, with the edges given at the near
+    // plane. useful for stereo, tiled rendering and shadow debugging. the
+    // aspect ratio is ignored because the edges already define it.
+    OffCentre {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+// which end of the 0..1 depth range the near plane ends up at. whatever
+// renders with a matrix has to agree with this, see clear_value and
+// compare_op.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthMode {
+    // near plane at 0, far plane at 1. clear depth to 1.0 and pass fragments
+    // with Less. this is what every pipeline in this repo is set up for.
+    Standard,
+    // near plane at 1, far plane at 0. combined with a floating point depth
+    // buffer (D32Sfloat) this spreads precision evenly over distance instead
+    // of spending almost all of it right in front of the camera, and makes an
+    // infinite far plane practically free. clear depth to 0.0 and pass
+    // fragments with Greater, so it only works with pipelines that let you
+    // change the depth compare op.
+    Reversed,
+}
+
+impl DepthMode {
+    pub fn clear_value(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::Reversed => 0.0,
+        }
+    }
+
+    pub fn compare_op(&self) -> Compare {
+        match self {
+            DepthMode::Standard => Compare::Less,
+            DepthMode::Reversed => Compare::Greater,
+        }
+    }
+}
+
+impl Default for DepthMode {
+    fn default() -> Self {
+        DepthMode::Standard
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            fov: 1.0,
+            near: 1.0,
+            far: 10_000.,
+        }
+    }
+}
+
+impl Projection {
+    pub fn matrix(&self, aspect_ratio: f32, depth: DepthMode) -> Mat4 {
+        match *self {
+            Projection::Perspective { fov, near, far } => {
+                perspective_vk(aspect_ratio, fov, near, far, depth)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect_ratio;
+                orthographic_vk(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                    depth,
+                )
+            }
+            Projection::OffCentre {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                far,
+            } => frustum_vk(left, right, bottom, top, near, far, depth),
+        }
+    }
+
+    pub fn near(&self) -> f32 {
+        match *self {
+            Projection::Perspective { near, .. }
+            | Projection::Orthographic { near, .. }
+            | Projection::OffCentre { near, .. } => near,
+        }
+    }
+
+    pub fn far(&self) -> f32 {
+        match *self {
+            Projection::Perspective { far, .. }
+            | Projection::Orthographic { far, .. }
+            | Projection::OffCentre { far, .. } => far,
+        }
+    }
+
+    pub fn set_clip_planes(&mut self, new_near: f32, new_far: f32) {
+        match self {
+            Projection::Perspective { near, far, .. }
+            | Projection::Orthographic { near, far, .. }
+            | Projection::OffCentre { near, far, .. } => {
+                *near = new_near;
+                *far = new_far;
+            }
+        }
+    }
+
+    pub fn slice_corners(&self, aspect_ratio: f32, near: f32, far: f32) -> [Vec3; 8] {
+        // the corners of the part of the view volume between the distances
+        // near and far, in view space. the 4 near corners come first, each
+        // group going bottom left, bottom right, top left, top right. far has
+        // to be finite, clamp it to something sensible for infinite
+        // projections.
+        let edges = |distance: f32| -> [f32; 4] {
+            // left, right, bottom, top at the given distance
+            match *self {
+                Projection::Perspective { fov, .. } => {
+                    let top = distance * (fov / 2.0).tan();
+                    let right = top * aspect_ratio;
+                    [-right, right, -top, top]
+                }
+                Projection::Orthographic { height, .. } => {
+                    let top = height / 2.0;
+                    let right = top * aspect_ratio;
+                    [-right, right, -top, top]
+                }
+                Projection::OffCentre {
+                    left,
+                    right,
+                    bottom,
+                    top,
+                    near: frustum_near,
+                    ..
+                } => {
+                    let s = distance / frustum_near;
+                    [left * s, right * s, bottom * s, top * s]
+                }
+            }
+        };
+
+        let mut corners = [vec3(0.0, 0.0, 0.0); 8];
+        for (i, &distance) in [near, far].iter().enumerate() {
+            let [left, right, bottom, top] = edges(distance);
+            corners[i * 4] = vec3(left, bottom, -distance);
+            corners[i * 4 + 1] = vec3(right, bottom, -distance);
+            corners[i * 4 + 2] = vec3(left, top, -distance);
+            corners[i * 4 + 3] = vec3(right, top, -distance);
+        }
+
+        corners
+    }
+
+    pub fn set_fov(&mut self, new_fov: f32) {
+        // switches to a perspective projection if it wasn't one already
+        match self {
+            Projection::Perspective { fov, .. } => *fov = new_fov,
+            _ => {
+                *self = Projection::Perspective {
+                    fov: new_fov,
+                    near: self.near(),
+                    far: self.far(),
+                }
+            }
+        }
+    }
+}
+
+pub fn perspective_vk(aspect_ratio: f32, fov: f32, near: f32, far: f32, depth: DepthMode) -> Mat4 {
+    // a symmetric frustum, fov is vertical and in radians
+    let top = near * (fov / 2.0).tan();
+    let right = top * aspect_ratio;
+
+    frustum_vk(-right, right, -top, top, near, far, depth)
+}
+
+pub fn frustum_vk(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+    depth: DepthMode,
+) -> Mat4 {
+    // left, right, bottom and top are the edges of the near plane, with y up
+    // like in view space. top ends up at y = -1 in clip space, because
+    // vulkan's y points down.
+    //
+    // w is always -z. depth is z_clip / w, so the third row only has to map
+    // -near and -far to the right ends of 0..1. if far is infinite the limit
+    // is used instead, which is why it has its own branch rather than
+    // producing NaNs.
+    let mut mat = Mat4::zeros();
+
+    mat[(0, 0)] = 2.0 * near / (right - left);
+    mat[(0, 2)] = (right + left) / (right - left);
+    mat[(1, 1)] = -2.0 * near / (top - bottom);
+    mat[(1, 2)] = -(top + bottom) / (top - bottom);
+    mat[(3, 2)] = -1.0;
+
+    let (z_scale, z_offset) = match (depth, far.is_infinite()) {
+        // depth = far (z + near) / (z (far - near))
+        (DepthMode::Standard, false) => (far / (near - far), near * far / (near - far)),
+        // depth = 1 + near / z
+        (DepthMode::Standard, true) => (-1.0, -near),
+        // depth = near (z + far) / (z (near - far))
+        (DepthMode::Reversed, false) => (near / (far - near), near * far / (far - near)),
+        // depth = -near / z
+        (DepthMode::Reversed, true) => (0.0, near),
+    };
+    mat[(2, 2)] = z_scale;
+    mat[(2, 3)] = z_offset;
+
+    mat
+}
+
+pub fn orthographic_vk(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+    depth: DepthMode,
+) -> Mat4 {
+    // the same conventions as frustum_vk, except that w stays 1 and depth is
+    // linear. an infinite far plane would squash everything onto one depth,
+    // so it isn't allowed here.
+    debug_assert!(far.is_finite(), "orthographic projections need a finite far plane");
+
+    let mut mat = Mat4::identity();
+
+    mat[(0, 0)] = 2.0 / (right - left);
+    mat[(0, 3)] = -(right + left) / (right - left);
+    mat[(1, 1)] = -2.0 / (top - bottom);
+    mat[(1, 3)] = (top + bottom) / (top - bottom);
+
+    match depth {
+        // depth = (-z - near) / (far - near)
+        DepthMode::Standard => {
+            mat[(2, 2)] = -1.0 / (far - near);
+            mat[(2, 3)] = -near / (far - near);
+        }
+        // depth = (z + far) / (far - near)
+        DepthMode::Reversed => {
+            mat[(2, 2)] = 1.0 / (far - near);
+            mat[(2, 3)] = far / (far - near);
+        }
+    }
+
+    mat
+}
+
+pub fn project_point(matrix: &Mat4, point: &Vec3) -> Vec3 {
+    // transforms a view space point to normalized device coordinates, handy
+    // for checking what a matrix does to a known point
+    let clip = matrix * vec4(point.x, point.y, point.z, 1.0);
+
+    vec3(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fov is 90 degrees, so the near plane spans -2..2 by -1..1 with an
+    // aspect ratio of 2
+    const FOV: f32 = std::f32::consts::FRAC_PI_2;
+
+    fn assert_projects_to(matrix: &Mat4, point: Vec3, expected: Vec3) {
+        let projected = project_point(matrix, &point);
+        assert!(
+            distance(&projected, &expected) < 1e-4,
+            "{:?} projected to {:?}, expected {:?}",
+            point,
+            projected,
+            expected
+        );
+    }
+
+    #[test]
+    fn perspective_standard() {
+        let mat = perspective_vk(2.0, FOV, 1.0, 100.0, DepthMode::Standard);
+
+        assert_projects_to(&mat, vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 0.0));
+        assert_projects_to(&mat, vec3(0.0, 0.0, -100.0), vec3(0.0, 0.0, 1.0));
+        // top right ends up at y = -1, vulkan's y points down
+        assert_projects_to(&mat, vec3(2.0, 1.0, -1.0), vec3(1.0, -1.0, 0.0));
+        assert_projects_to(&mat, vec3(-200.0, -100.0, -100.0), vec3(-1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn perspective_reversed() {
+        let mat = perspective_vk(2.0, FOV, 1.0, 100.0, DepthMode::Reversed);
+
+        assert_projects_to(&mat, vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 1.0));
+        assert_projects_to(&mat, vec3(0.0, 0.0, -100.0), vec3(0.0, 0.0, 0.0));
+        assert_projects_to(&mat, vec3(-2.0, 1.0, -1.0), vec3(-1.0, -1.0, 1.0));
+        assert_projects_to(&mat, vec3(200.0, -100.0, -100.0), vec3(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn perspective_infinite() {
+        let far = std::f32::INFINITY;
+        let standard = perspective_vk(2.0, FOV, 1.0, far, DepthMode::Standard);
+        let reversed = perspective_vk(2.0, FOV, 1.0, far, DepthMode::Reversed);
+
+        assert_projects_to(&standard, vec3(2.0, 1.0, -1.0), vec3(1.0, -1.0, 0.0));
+        assert_projects_to(&reversed, vec3(2.0, 1.0, -1.0), vec3(1.0, -1.0, 1.0));
+
+        // far away points approach the far end of the range but never pass it
+        let distant = vec3(0.0, 0.0, -1.0e6);
+        assert_projects_to(&standard, distant, vec3(0.0, 0.0, 1.0));
+        assert_projects_to(&reversed, distant, vec3(0.0, 0.0, 0.0));
+        assert!(project_point(&reversed, &distant).z > 0.0);
+
+        let corner = vec3(-2.0e6, -1.0e6, -1.0e6);
+        assert_projects_to(&reversed, corner, vec3(-1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn orthographic() {
+        let standard = orthographic_vk(-2.0, 2.0, -1.0, 1.0, 1.0, 11.0, DepthMode::Standard);
+        let reversed = orthographic_vk(-2.0, 2.0, -1.0, 1.0, 1.0, 11.0, DepthMode::Reversed);
+
+        assert_projects_to(&standard, vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 0.0));
+        assert_projects_to(&standard, vec3(0.0, 0.0, -11.0), vec3(0.0, 0.0, 1.0));
+        assert_projects_to(&standard, vec3(2.0, 1.0, -1.0), vec3(1.0, -1.0, 0.0));
+        assert_projects_to(&standard, vec3(-2.0, -1.0, -11.0), vec3(-1.0, 1.0, 1.0));
+
+        assert_projects_to(&reversed, vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 1.0));
+        assert_projects_to(&reversed, vec3(0.0, 0.0, -11.0), vec3(0.0, 0.0, 0.0));
+        assert_projects_to(&reversed, vec3(2.0, -1.0, -6.0), vec3(1.0, 1.0, 0.5));
+    }
+}
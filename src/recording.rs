@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use nalgebra_glm::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::LoadError;
+use crate::input::{CameraInput, Modifiers};
+
+// a list of per-frame camera inputs. recording the input instead of the
+// resulting pose means it works for any camera, and playing it back gives
+// exactly the same movement as long as the camera starts out the same way.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+// CameraInput, but in a form serde understands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub look: [f32; 2],
+    pub movement: [f32; 3],
+    // older recordings don't have this
+    #[serde(default)]
+    pub roll: f32,
+    pub zoom: f32,
+    pub pan: bool,
+    pub sprint: bool,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub dimensions: [u32; 2],
+    pub delta: f32,
+}
+
+impl From<&CameraInput> for RecordedFrame {
+    fn from(input: &CameraInput) -> Self {
+        Self {
+            look: input.look,
+            movement: input.movement.into(),
+            roll: input.roll,
+            zoom: input.zoom,
+            pan: input.pan,
+            sprint: input.sprint,
+            shift: input.modifiers.shift,
+            ctrl: input.modifiers.ctrl,
+            alt: input.modifiers.alt,
+            dimensions: input.dimensions,
+            delta: input.delta,
+        }
+    }
+}
+
+impl From<&RecordedFrame> for CameraInput {
+    fn from(frame: &RecordedFrame) -> Self {
+        Self {
+            look: frame.look,
+            movement: make_vec3(&frame.movement),
+            roll: frame.roll,
+            zoom: frame.zoom,
+            pan: frame.pan,
+            sprint: frame.sprint,
+            modifiers: Modifiers {
+                shift: frame.shift,
+                ctrl: frame.ctrl,
+                alt: frame.alt,
+                logo: false,
+            },
+            dimensions: frame.dimensions,
+            delta: frame.delta,
+        }
+    }
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, input: &CameraInput) {
+        self.frames.push(input.into());
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.delta).sum()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        // RON, so recordings can be looked at and trimmed by hand
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        fs::write(path, text)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        let text = fs::read_to_string(path).map_err(|e| LoadError::from_io(path, e))?;
+
+        ron::de::from_str(&text).map_err(|e| LoadError::Undecodable {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+}
+
+// hands out a recording's frames one at a time. every frame uses the timestep
+// it was recorded with rather than however long the frame actually took, so
+// the camera ends up in the same place on every run no matter how fast the
+// machine is, and the run always lasts exactly as many frames as were
+// recorded.
+pub struct Playback {
+    recording: Recording,
+    frame: usize,
+}
+
+impl Playback {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+        }
+    }
+
+    pub fn next_input(&mut self) -> Option<CameraInput> {
+        let input = self.recording.frames.get(self.frame).map(CameraInput::from);
+        self.frame += 1;
+
+        input
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+
+    pub fn frames_played(&self) -> usize {
+        self.frame.min(self.recording.frames.len())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.recording.frames.len()
+    }
+}
@@ -0,0 +1,348 @@
+use render_engine::render_passes;
+use render_engine::system::{Pass, System};
+use render_engine::{Device, Format, Image, Queue, RenderPass};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::LoadError;
+use crate::shadow::{moments_blur_pass, moments_pass};
+
+// a System's passes and custom images, stored as RON so pass layouts can be
+// changed without recompiling. see graphs/pretty/ for examples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderGraph {
+    // in the order they run
+    pub passes: Vec<PassDescription>,
+    // images that aren't the size of the window, like shadow maps. every one
+    // of them has to be created by some pass too.
+    #[serde(default)]
+    pub images: Vec<ImageDescription>,
+    // the tag shown on screen
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PassDescription {
+    pub name: String,
+    pub render_pass: RenderPassKind,
+    // tags of the images this pass renders to, in attachment order
+    #[serde(default)]
+    pub creates: Vec<String>,
+    // tags of the images it samples, they end up in set 0
+    #[serde(default)]
+    pub needs: Vec<String>,
+}
+
+// render-engine's render passes, plus the shadow ones from this crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RenderPassKind {
+    Basic,
+    WithDepth,
+    OnlyDepth,
+    ReadDepth,
+    // with this many samples
+    MultisampledWithDepth(u32),
+    // see shadow::moments_pass and moments_blur_pass
+    Moments(ImageFormat),
+    MomentsBlur(ImageFormat),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageDescription {
+    pub tag: String,
+    pub format: ImageFormat,
+    pub size: ImageSize,
+    // size resolved when loading
+    #[serde(skip)]
+    dimensions: [u32; 2],
+}
+
+// the formats the bins actually use. vulkano's Format doesn't implement
+// serde's traits, so this stands in for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ImageFormat {
+    D32Sfloat,
+    R32Sfloat,
+    R32G32Sfloat,
+    R16G16B16A16Sfloat,
+    R8G8B8A8Unorm,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ImageSize {
+    // width and height in texels
+    Fixed(u32, u32),
+    // a size only the bin knows, like a shadow cubemap's dimensions. the bin
+    // passes these by name when loading the graph.
+    Named(String),
+}
+
+impl RenderGraph {
+    pub fn load(path: &Path, sizes: &HashMap<&str, [u32; 2]>) -> Result<Self, LoadError> {
+        let text = fs::read_to_string(path).map_err(|e| LoadError::from_io(path, e))?;
+
+        Self::parse(&text, sizes).map_err(|reason| LoadError::Undecodable {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    // parses and checks a graph. errors say what's wrong with it.
+    pub fn parse(text: &str, sizes: &HashMap<&str, [u32; 2]>) -> Result<Self, String> {
+        let mut graph: Self = ron::de::from_str(text).map_err(|e| e.to_string())?;
+
+        for image in graph.images.iter_mut() {
+            image.dimensions = match &image.size {
+                ImageSize::Fixed(width, height) => [*width, *height],
+                ImageSize::Named(name) => *sizes.get(name.as_str()).ok_or_else(|| {
+                    format!("image {} has size {}, which isn't known", image.tag, name)
+                })?,
+            };
+        }
+
+        graph.validate()?;
+
+        Ok(graph)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // custom images are always there, even before the pass that creates
+        // them has run, so passes can read last frame's contents
+        let mut available: Vec<&str> = self.images.iter().map(|i| i.tag.as_str()).collect();
+        let mut names: Vec<&str> = vec![];
+
+        for pass in self.passes.iter() {
+            if names.contains(&pass.name.as_str()) {
+                return Err(format!("there's more than one pass called {}", pass.name));
+            }
+            names.push(&pass.name);
+
+            if let Some(tag) = pass
+                .needs
+                .iter()
+                .find(|tag| !available.contains(&tag.as_str()))
+            {
+                return Err(format!(
+                    "pass {} needs {}, which no earlier pass creates",
+                    pass.name, tag
+                ));
+            }
+            available.extend(pass.creates.iter().map(|tag| tag.as_str()));
+        }
+
+        if !available.contains(&self.output.as_str()) {
+            return Err(format!("nothing creates the output, {}", self.output));
+        }
+
+        // otherwise a typo in some pass's creates goes unnoticed, the image
+        // is there anyway and just never gets drawn to
+        if let Some(image) = self.images.iter().find(|image| {
+            !self
+                .passes
+                .iter()
+                .any(|pass| pass.creates.contains(&image.tag))
+        }) {
+            return Err(format!("no pass creates the image {}", image.tag));
+        }
+
+        Ok(())
+    }
+
+    // the same graph minus some passes, for example to skip shadow passes on
+    // frames where the shadow maps haven't changed
+    pub fn without_passes(&self, names: &[&str]) -> Self {
+        Self {
+            passes: self
+                .passes
+                .iter()
+                .filter(|pass| !names.contains(&pass.name.as_str()))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    // creating the images separately means several systems can share them
+    pub fn create_images(&self, device: Device) -> Result<HashMap<&'static str, Image>, LoadError> {
+        self.images
+            .iter()
+            .map(|image| {
+                let created: Image = vulkano::image::AttachmentImage::sampled(
+                    device.clone(),
+                    image.dimensions,
+                    image.format.into(),
+                )
+                .map_err(|e| LoadError::Upload(format!("image {}: {}", image.tag, e)))?;
+
+                Ok((leak(&image.tag), created))
+            })
+            .collect()
+    }
+
+    pub fn build(&self, queue: Queue, custom_images: &HashMap<&'static str, Image>) -> System {
+        // passes of the same kind share a render pass, like they would if
+        // they were written out by hand
+        let device = queue.device().clone();
+        let mut shared: HashMap<RenderPassKind, RenderPass> = HashMap::new();
+
+        let passes = self
+            .passes
+            .iter()
+            .map(|pass| {
+                let render_pass = shared
+                    .entry(pass.render_pass)
+                    .or_insert_with(|| pass.render_pass.create(device.clone()))
+                    .clone();
+
+                Pass {
+                    name: leak(&pass.name),
+                    images_created_tags: pass.creates.iter().map(|tag| leak(tag)).collect(),
+                    images_needed_tags: pass.needs.iter().map(|tag| leak(tag)).collect(),
+                    render_pass,
+                }
+            })
+            .collect();
+
+        System::new(queue, passes, custom_images.clone(), leak(&self.output))
+    }
+}
+
+impl RenderPassKind {
+    pub fn create(&self, device: Device) -> RenderPass {
+        match *self {
+            RenderPassKind::Basic => render_passes::basic(device),
+            RenderPassKind::WithDepth => render_passes::with_depth(device),
+            RenderPassKind::OnlyDepth => render_passes::only_depth(device),
+            RenderPassKind::ReadDepth => render_passes::read_depth(device),
+            RenderPassKind::MultisampledWithDepth(samples) => {
+                render_passes::multisampled_with_depth(device, samples)
+            }
+            RenderPassKind::Moments(format) => moments_pass(device, format.into()),
+            RenderPassKind::MomentsBlur(format) => moments_blur_pass(device, format.into()),
+        }
+    }
+}
+
+impl From<ImageFormat> for Format {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::D32Sfloat => Format::D32Sfloat,
+            ImageFormat::R32Sfloat => Format::R32Sfloat,
+            ImageFormat::R32G32Sfloat => Format::R32G32Sfloat,
+            ImageFormat::R16G16B16A16Sfloat => Format::R16G16B16A16Sfloat,
+            ImageFormat::R8G8B8A8Unorm => Format::R8G8B8A8Unorm,
+        }
+    }
+}
+
+// System and Pass want &'static str tags. a graph is only loaded a handful
+// of times per run, so leaking its strings costs a few bytes each time.
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_string().into_boxed_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relative_path;
+
+    fn sizes() -> HashMap<&'static str, [u32; 2]> {
+        let mut sizes = HashMap::new();
+        sizes.insert("shadow_cubemap", [1024, 512]);
+        sizes
+    }
+
+    #[test]
+    fn pretty_graphs() {
+        for name in &["depth", "vsm", "esm"] {
+            let path = relative_path(&format!("graphs/pretty/{}.ron", name));
+            let graph = RenderGraph::load(&path, &sizes())
+                .unwrap_or_else(|e| panic!("{} didn't load: {}", name, e));
+
+            assert_eq!(graph.output, "color");
+            assert!(graph
+                .images
+                .iter()
+                .all(|image| image.dimensions == [1024, 512]));
+            // pretty drops these on frames where the shadows haven't changed
+            let cached = graph.without_passes(&["shadow", "shadow_blur"]);
+            assert!(cached
+                .passes
+                .iter()
+                .all(|pass| !pass.name.starts_with("shadow")));
+            assert_eq!(cached.images.len(), graph.images.len());
+        }
+    }
+
+    #[test]
+    fn duplicate_pass_names() {
+        let text = r#"(
+            passes: [
+                (name: "geometry", render_pass: WithDepth, creates: ["color", "depth"]),
+                (name: "geometry", render_pass: Basic, creates: ["blurred"], needs: ["color"]),
+            ],
+            output: "blurred",
+        )"#;
+
+        assert_eq!(
+            RenderGraph::parse(text, &sizes()).unwrap_err(),
+            "there's more than one pass called geometry"
+        );
+    }
+
+    #[test]
+    fn needs_before_creates() {
+        let text = r#"(
+            passes: [
+                (name: "blur", render_pass: Basic, creates: ["blurred"], needs: ["color"]),
+                (name: "geometry", render_pass: WithDepth, creates: ["color", "depth"]),
+            ],
+            output: "blurred",
+        )"#;
+
+        assert_eq!(
+            RenderGraph::parse(text, &sizes()).unwrap_err(),
+            "pass blur needs color, which no earlier pass creates"
+        );
+    }
+
+    #[test]
+    fn missing_output() {
+        let text = r#"(
+            passes: [
+                (name: "geometry", render_pass: WithDepth, creates: ["color", "depth"]),
+            ],
+            output: "colour",
+        )"#;
+
+        assert_eq!(
+            RenderGraph::parse(text, &sizes()).unwrap_err(),
+            "nothing creates the output, colour"
+        );
+    }
+
+    #[test]
+    fn uncreated_image() {
+        // the shadow pass writes to a misspelled tag, so shadow_map would
+        // never be drawn to
+        let text = r#"(
+            images: [
+                (tag: "shadow_map", format: D32Sfloat, size: Named("shadow_cubemap")),
+            ],
+            passes: [
+                (name: "shadow", render_pass: OnlyDepth, creates: ["shadow_mpa"]),
+                (name: "geometry", render_pass: WithDepth, creates: ["color", "depth"], needs: ["shadow_map"]),
+            ],
+            output: "color",
+        )"#;
+
+        assert_eq!(
+            RenderGraph::parse(text, &sizes()).unwrap_err(),
+            "no pass creates the image shadow_map"
+        );
+    }
+}
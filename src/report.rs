@@ -0,0 +1,172 @@
+use serde::Serialize;
+
+use std::fmt;
+use std::path::PathBuf;
+
+// filled in while loading a scene so asset problems can be looked at all at
+// once instead of being scattered through the log. print it with {} for a
+// table or use to_json for CI.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadReport {
+    pub models: Vec<ModelReport>,
+    pub materials: Vec<MaterialReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelReport {
+    pub name: String,
+    pub vertices: usize,
+    pub triangles: usize,
+    // index into materials that the model ended up using
+    pub material: usize,
+    // true if the model had no material id or an invalid one, so material 0
+    // was used instead
+    pub material_fallback: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialReport {
+    pub name: String,
+    pub diffuse: TextureReport,
+    pub specular: TextureReport,
+    pub normal: TextureReport,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextureReport {
+    pub source: TextureSource,
+    // the file that was actually loaded, so the placeholder for anything but
+    // TextureSource::File
+    pub path: PathBuf,
+    pub decode_ms: f32,
+    // bytes uploaded to the GPU, including mip levels
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextureSource {
+    // loaded from the file the material asked for
+    File,
+    // the material has no texture in this slot, so a placeholder was used
+    Fallback,
+    // the material names a file that doesn't exist, so a placeholder was used
+    Missing,
+}
+
+impl LoadReport {
+    pub fn add_models(&mut self, models: &[tobj::Model], material_count: usize) {
+        // records vertex and triangle counts for every model and resolves
+        // which material each one uses
+        self.models.extend(models.iter().map(|model| {
+            let (material, material_fallback) = match model.mesh.material_id {
+                Some(idx) if idx < material_count => (idx, false),
+                _ => (0, true),
+            };
+
+            ModelReport {
+                name: model.name.clone(),
+                vertices: model.mesh.positions.len() / 3,
+                triangles: model.mesh.indices.len() / 3,
+                material,
+                material_fallback,
+            }
+        }));
+    }
+
+    pub fn total_triangles(&self) -> usize {
+        self.models.iter().map(|model| model.triangles).sum()
+    }
+
+    pub fn total_texture_bytes(&self) -> usize {
+        self.textures().map(|tex| tex.bytes).sum()
+    }
+
+    pub fn total_decode_ms(&self) -> f32 {
+        self.textures().map(|tex| tex.decode_ms).sum()
+    }
+
+    pub fn problem_count(&self) -> usize {
+        // anything that fell back to a default, which is what you usually want
+        // to look at first
+        let models = self.models.iter().filter(|m| m.material_fallback).count();
+        let textures = self
+            .textures()
+            .filter(|tex| tex.source != TextureSource::File)
+            .count();
+
+        models + textures
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Couldn't serialize load report")
+    }
+
+    fn textures(&self) -> impl Iterator<Item = &TextureReport> {
+        self.materials
+            .iter()
+            .flat_map(|mat| vec![&mat.diffuse, &mat.specular, &mat.normal])
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} models", self.models.len())?;
+        writeln!(
+            f,
+            "{:<32} {:>10} {:>10} {:>9}",
+            "name", "vertices", "triangles", "material"
+        )?;
+        for model in self.models.iter() {
+            let fallback = if model.material_fallback { "*" } else { "" };
+            writeln!(
+                f,
+                "{:<32} {:>10} {:>10} {:>8}{:1}",
+                model.name, model.vertices, model.triangles, model.material, fallback
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{} materials", self.materials.len())?;
+        writeln!(
+            f,
+            "{:<24} {:<9} {:<9} {:>10} {:>10}  {}",
+            "name", "slot", "source", "decode ms", "KiB", "path"
+        )?;
+        for mat in self.materials.iter() {
+            let slots = [
+                ("diffuse", &mat.diffuse),
+                ("specular", &mat.specular),
+                ("normal", &mat.normal),
+            ];
+            for (slot, tex) in slots.iter() {
+                let source = match tex.source {
+                    TextureSource::File => "file",
+                    TextureSource::Fallback => "fallback",
+                    TextureSource::Missing => "missing",
+                };
+                writeln!(
+                    f,
+                    "{:<24} {:<9} {:<9} {:>10.2} {:>10}  {:?}",
+                    mat.name,
+                    slot,
+                    source,
+                    tex.decode_ms,
+                    tex.bytes / 1024,
+                    tex.path
+                )?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} triangles, {:.1} MiB of textures decoded in {:.0} ms, {} problems \
+             (* = material fallback)",
+            self.total_triangles(),
+            self.total_texture_bytes() as f32 / (1024.0 * 1024.0),
+            self.total_decode_ms(),
+            self.problem_count()
+        )
+    }
+}
@@ -0,0 +1,242 @@
+use vulkano::command_buffer::DynamicState;
+
+use nalgebra_glm::*;
+
+use super::dynamic_state_for_bounds;
+use crate::projection::Projection;
+
+// packs shadow maps for any number of lights into one square depth image, so
+// another shadowed light is another request instead of another image and
+// pass. tiles are square and a power of two in size. each light asks for as
+// many tiles as it needs (1 for a spot light, 6 for a point light, one per
+// cascade) at the resolution it would like, and gets smaller ones if they
+// don't all fit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowAtlas {
+    // width and height of the atlas in texels, a power of two
+    pub size: u32,
+    // tiles are never smaller than this, lights that don't fit at this size
+    // don't get shadows
+    pub min_tile: u32,
+    pub max_tile: u32,
+}
+
+// what one light wants from the atlas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileRequest {
+    pub tiles: usize,
+    // size of each tile in texels, rounded up to a power of two
+    pub resolution: u32,
+    // when the atlas is full, lower priority lights give up resolution first
+    pub priority: f32,
+}
+
+// a square part of the atlas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasTile {
+    // top left corner in texels
+    pub origin: [u32; 2],
+    pub size: u32,
+}
+
+impl Default for ShadowAtlas {
+    fn default() -> Self {
+        Self {
+            size: 4096,
+            min_tile: 128,
+            max_tile: 1024,
+        }
+    }
+}
+
+impl ShadowAtlas {
+    pub fn new(size: u32) -> Self {
+        let size = size.max(1).next_power_of_two();
+
+        Self {
+            size,
+            min_tile: Self::default().min_tile.min(size),
+            max_tile: Self::default().max_tile.min(size),
+        }
+    }
+
+    pub fn with_tile_range(mut self, min_tile: u32, max_tile: u32) -> Self {
+        self.max_tile = max_tile.max(1).next_power_of_two().min(self.size);
+        self.min_tile = min_tile.max(1).next_power_of_two().min(self.max_tile);
+        self
+    }
+
+    // size of the depth image to create
+    pub fn dimensions(&self) -> [u32; 2] {
+        [self.size, self.size]
+    }
+
+    // picks a tile size from how much of the screen a light's reach covers,
+    // see screen_coverage. priority scales it, so 2 asks for twice the
+    // resolution.
+    pub fn resolution_for(&self, coverage: f32, priority: f32) -> u32 {
+        let ideal = self.max_tile as f32 * coverage.max(0.0).min(1.0) * priority.max(0.0);
+
+        (ideal.ceil() as u32)
+            .max(1)
+            .next_power_of_two()
+            .max(self.min_tile)
+            .min(self.max_tile)
+    }
+
+    // returns each request's tiles, in the same order as the requests. None
+    // means the light didn't fit even with the smallest tiles.
+    pub fn allocate(&self, requests: &[TileRequest]) -> Vec<Option<Vec<AtlasTile>>> {
+        let clamp = |resolution: u32| {
+            resolution
+                .max(1)
+                .next_power_of_two()
+                .max(self.min_tile)
+                .min(self.max_tile)
+        };
+        let mut sizes: Vec<Option<u32>> = requests
+            .iter()
+            .map(|request| Some(clamp(request.resolution)))
+            .collect();
+
+        // area is counted in min_tile sized cells, which keeps the numbers
+        // small
+        let cells = |size: u32| (size / self.min_tile).pow(2) as u64;
+        let capacity = cells(self.size);
+        let needed = |sizes: &[Option<u32>]| -> u64 {
+            sizes
+                .iter()
+                .zip(requests.iter())
+                .map(|(size, request)| size.map(|s| cells(s) * request.tiles as u64).unwrap_or(0))
+                .sum()
+        };
+
+        // shrink until everything fits: halve the biggest tiles, and among
+        // those the least important light's. if everything is already as
+        // small as it gets, drop the least important light.
+        while needed(&sizes) > capacity {
+            let shrink = (0..requests.len())
+                .filter(|&idx| sizes[idx].map(|s| s > self.min_tile) == Some(true))
+                .max_by(|&a, &b| {
+                    sizes[a].cmp(&sizes[b]).then(
+                        requests[b]
+                            .priority
+                            .partial_cmp(&requests[a].priority)
+                            .unwrap_or(std::cmp::Ordering::Equal),
+                    )
+                });
+
+            match shrink {
+                Some(idx) => sizes[idx] = sizes[idx].map(|s| s / 2),
+                None => {
+                    let drop = (0..requests.len())
+                        .filter(|&idx| sizes[idx].is_some())
+                        .min_by(|&a, &b| {
+                            requests[a]
+                                .priority
+                                .partial_cmp(&requests[b].priority)
+                                .unwrap_or(std::cmp::Ordering::Equal)
+                        });
+                    match drop {
+                        Some(idx) => sizes[idx] = None,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        // place the biggest tiles first, walking the atlas in Z order. every
+        // tile then starts at a multiple of its own area, which in Z order is
+        // always a properly aligned square, so nothing overlaps and there are
+        // no gaps.
+        let mut order: Vec<usize> = (0..requests.len())
+            .filter(|&idx| sizes[idx].is_some())
+            .collect();
+        order.sort_by(|&a, &b| sizes[b].cmp(&sizes[a]));
+
+        let mut tiles: Vec<Option<Vec<AtlasTile>>> = vec![None; requests.len()];
+        let mut next_cell = 0;
+        for idx in order {
+            let size = sizes[idx].unwrap_or(self.min_tile);
+            let allocated = (0..requests[idx].tiles)
+                .map(|_| {
+                    let cell = morton_decode(next_cell);
+                    next_cell += cells(size);
+
+                    AtlasTile {
+                        origin: [cell[0] * self.min_tile, cell[1] * self.min_tile],
+                        size,
+                    }
+                })
+                .collect();
+            tiles[idx] = Some(allocated);
+        }
+
+        tiles
+    }
+}
+
+impl AtlasTile {
+    // for rendering casters to just this tile
+    pub fn viewport(&self) -> DynamicState {
+        dynamic_state_for_bounds(
+            [self.origin[0] as f32, self.origin[1] as f32],
+            [self.size as f32, self.size as f32],
+        )
+    }
+
+    // scale and offset taking the tile's 0..1 uv to the atlas' uv:
+    // atlas_uv = uv * xy + zw
+    pub fn uv_transform(&self, atlas: &ShadowAtlas) -> [f32; 4] {
+        let size = atlas.size as f32;
+
+        [
+            self.size as f32 / size,
+            self.size as f32 / size,
+            self.origin[0] as f32 / size,
+            self.origin[1] as f32 / size,
+        ]
+    }
+}
+
+// roughly what fraction of the screen's height a sphere covers, 0..1. lights
+// whose range covers more of the screen deserve more shadow map texels.
+pub fn screen_coverage(
+    camera_position: &Vec3,
+    projection: &Projection,
+    center: &Vec3,
+    radius: f32,
+) -> f32 {
+    let dist = distance(camera_position, center);
+    let fov = match *projection {
+        Projection::Perspective { fov, .. } => fov,
+        Projection::OffCentre {
+            bottom, top, near, ..
+        } => (top / near).atan() - (bottom / near).atan(),
+        // size on screen doesn't depend on distance
+        Projection::Orthographic { height, .. } => return (radius * 2.0 / height).min(1.0),
+    };
+    if dist <= radius {
+        return 1.0;
+    }
+
+    // angular radius of the sphere compared to half the field of view
+    let angle = (radius / dist).asin();
+
+    (angle / (fov * 0.5)).min(1.0)
+}
+
+fn morton_decode(index: u64) -> [u32; 2] {
+    // even bits are x, odd bits are y
+    let compact = |mut v: u64| {
+        v &= 0x5555_5555_5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333_3333_3333;
+        v = (v | (v >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v >> 4)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v >> 8)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v >> 16)) & 0x0000_0000_ffff_ffff;
+        v as u32
+    };
+
+    [compact(index), compact(index >> 1)]
+}
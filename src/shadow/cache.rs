@@ -0,0 +1,82 @@
+use nalgebra_glm::*;
+
+use crate::culling::{Aabb, Sphere};
+
+// remembers what a shadow map was last rendered with, so it's only rendered
+// again when something it shows has changed. that's when the light moves or
+// its range changes, or a caster within its range moves, changes or appears.
+// casters out of range can do whatever they like.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowCache {
+    rendered: Option<Rendered>,
+}
+
+// a caster as far as the cache is concerned: its world space bounds and a
+// revision that has to change whenever the caster does in a way its bounds
+// don't show, like a new model matrix that only rotates it. static casters
+// can just use 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CasterState {
+    pub bounds: Aabb,
+    pub revision: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rendered {
+    light_position: Vec3,
+    range: f32,
+    // index and state of every caster that was in range
+    casters: Vec<(usize, CasterState)>,
+}
+
+impl ShadowCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // true if the shadow map has to be rendered this frame. after it returns
+    // true the cache assumes it was, so only ask once per frame.
+    pub fn needs_update(
+        &mut self,
+        light_position: Vec3,
+        range: f32,
+        casters: &[CasterState],
+    ) -> bool {
+        let reach = Sphere {
+            center: light_position,
+            radius: range,
+        };
+        let current = Rendered {
+            light_position,
+            range,
+            casters: casters
+                .iter()
+                .enumerate()
+                .filter(|(_, caster)| reach.intersects_aabb(&caster.bounds))
+                .map(|(idx, caster)| (idx, *caster))
+                .collect(),
+        };
+
+        if self.rendered.as_ref() == Some(&current) {
+            false
+        } else {
+            self.rendered = Some(current);
+            true
+        }
+    }
+
+    // forces the next needs_update to return true, for when the shadow map
+    // itself was recreated
+    pub fn invalidate(&mut self) {
+        self.rendered = None;
+    }
+}
+
+impl CasterState {
+    pub fn new(bounds: Aabb) -> Self {
+        Self {
+            bounds,
+            revision: 0,
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use render_engine::collection::Data;
+
+use vulkano::command_buffer::DynamicState;
+
+use nalgebra_glm::*;
+
+use super::dynamic_state_for_bounds;
+use crate::projection::{orthographic_vk, DepthMode, Projection};
+
+// the shaders declare arrays of this size, so it's a hard limit
+pub const MAX_CASCADES: usize = 4;
+
+// cascaded shadow maps for a directional light. the part of the camera's view
+// that gets shadows is cut into slices along the view direction, and each
+// slice gets its own orthographic shadow map. near slices are small, so they
+// get far more texels per unit than one shadow map for everything would.
+//
+// the maps are packed side by side into one texture, cascade 0 on the left,
+// the same way ShadowCubemap packs its faces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CascadedShadowMap {
+    // 1..=MAX_CASCADES
+    pub cascade_count: usize,
+    // size of each cascade's shadow map in texels
+    pub resolution: u32,
+    // where the splits go: 0 spaces them evenly, 1 logarithmically. log splits
+    // match how perspective shrinks things but make the far cascades huge.
+    pub split_lambda: f32,
+    // how far from the camera shadows reach. cameras often have a very far or
+    // infinite far plane, and shadows out there aren't worth the texels.
+    pub max_distance: f32,
+    // fraction of each cascade over which it fades into the next one, so the
+    // switch in resolution isn't a visible line
+    pub blend: f32,
+    // how far beyond a slice casters are still picked up, towards the light
+    pub caster_margin: f32,
+}
+
+// one slice of the camera's view and the shadow map covering it
+#[derive(Debug, Clone)]
+pub struct Cascade {
+    pub index: usize,
+    // view space distances the slice starts and ends at
+    pub near: f32,
+    pub far: f32,
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub dynamic_state: DynamicState,
+}
+
+impl Cascade {
+    pub fn view_projection(&self) -> Mat4 {
+        self.proj * self.view
+    }
+}
+
+// what the shaders need to pick a cascade and sample it, see lit_amount in
+// shaders/obj-viewer/frag.glsl
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CascadeParams {
+    pub view_proj: [[[f32; 4]; 4]; MAX_CASCADES],
+    // view space distance each cascade ends at
+    pub splits: [f32; MAX_CASCADES],
+    // cascade count, blend fraction, 1 / cascade count, unused
+    pub info: [f32; 4],
+}
+
+impl Data for CascadeParams {}
+
+impl Default for CascadedShadowMap {
+    fn default() -> Self {
+        Self {
+            cascade_count: 4,
+            resolution: 1024,
+            split_lambda: 0.75,
+            max_distance: 100.0,
+            blend: 0.1,
+            caster_margin: 50.0,
+        }
+    }
+}
+
+impl CascadedShadowMap {
+    pub fn new(cascade_count: usize) -> Self {
+        Self {
+            cascade_count: cascade_count.max(1).min(MAX_CASCADES),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_resolution(mut self, resolution: u32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn with_split_lambda(mut self, lambda: f32) -> Self {
+        self.split_lambda = lambda.max(0.0).min(1.0);
+        self
+    }
+
+    pub fn with_max_distance(mut self, distance: f32) -> Self {
+        self.max_distance = distance;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: f32) -> Self {
+        self.blend = blend.max(0.0).min(1.0);
+        self
+    }
+
+    pub fn with_caster_margin(mut self, margin: f32) -> Self {
+        self.caster_margin = margin;
+        self
+    }
+
+    // size of the texture to create for all cascades
+    pub fn dimensions(&self) -> [u32; 2] {
+        [self.resolution * self.cascade_count as u32, self.resolution]
+    }
+
+    pub fn cascade_viewport(&self, index: usize) -> DynamicState {
+        let size = self.resolution as f32;
+
+        dynamic_state_for_bounds([index as f32 * size, 0.0], [size, size])
+    }
+
+    pub fn cascades(
+        &self,
+        camera_view: &Mat4,
+        projection: &Projection,
+        aspect_ratio: f32,
+        light_direction: &Vec3,
+    ) -> Vec<Cascade> {
+        // light_direction points towards the light, like the direction in
+        // obj-viewer's Light
+        let near = projection.near();
+        let far = projection.far().min(self.max_distance).max(near);
+        let splits = split_distances(near, far, self.cascade_count, self.split_lambda);
+
+        let inverse_view = inverse(camera_view);
+        let to_light = normalize(light_direction);
+        // look_at can't use an up vector parallel to the view direction
+        let up = if to_light.y.abs() > 0.99 {
+            vec3(1.0, 0.0, 0.0)
+        } else {
+            vec3(0.0, 1.0, 0.0)
+        };
+
+        splits
+            .windows(2)
+            .enumerate()
+            .map(|(index, split)| {
+                let corners = projection.slice_corners(aspect_ratio, split[0], split[1]);
+                let world_corners: Vec<Vec3> = corners
+                    .iter()
+                    .map(|c| (inverse_view * vec4(c.x, c.y, c.z, 1.0)).xyz())
+                    .collect();
+
+                // fit a sphere rather than a box around the slice. its size
+                // doesn't change when the camera turns, so the shadow map's
+                // texels stay the same size and don't shimmer. rounding the
+                // radius keeps float noise from changing it every frame.
+                let center = world_corners
+                    .iter()
+                    .fold(vec3(0.0, 0.0, 0.0), |acc, c| acc + c)
+                    / world_corners.len() as f32;
+                let radius = world_corners
+                    .iter()
+                    .map(|c| distance(c, &center))
+                    .fold(0.0, f32::max);
+                let radius = (radius * 16.0).ceil() / 16.0;
+
+                // back the light off far enough to catch casters outside the
+                // slice that still throw shadows into it
+                let depth = radius * 2.0 + self.caster_margin;
+                let eye = center + to_light * (radius + self.caster_margin);
+                let view = look_at(&eye, &center, &up);
+                let mut proj = orthographic_vk(
+                    -radius,
+                    radius,
+                    -radius,
+                    radius,
+                    0.0,
+                    depth,
+                    DepthMode::Standard,
+                );
+
+                // snap the projection to whole texels. otherwise moving the
+                // camera moves the shadow map by fractions of a texel, and
+                // every shadow edge crawls.
+                let origin = proj * view * vec4(0.0, 0.0, 0.0, 1.0);
+                let texels = self.resolution as f32 / 2.0;
+                let snapped = vec2(
+                    (origin.x * texels).round() / texels,
+                    (origin.y * texels).round() / texels,
+                );
+                proj[(0, 3)] += snapped.x - origin.x;
+                proj[(1, 3)] += snapped.y - origin.y;
+
+                Cascade {
+                    index,
+                    near: split[0],
+                    far: split[1],
+                    view,
+                    proj,
+                    dynamic_state: self.cascade_viewport(index),
+                }
+            })
+            .collect()
+    }
+
+    pub fn shader_params(&self, cascades: &[Cascade]) -> CascadeParams {
+        let mut view_proj = [[[0.0; 4]; 4]; MAX_CASCADES];
+        // unused cascades end at infinity so the shader never picks them
+        let mut splits = [std::f32::INFINITY; MAX_CASCADES];
+        for (cascade, (matrix, split)) in cascades
+            .iter()
+            .zip(view_proj.iter_mut().zip(splits.iter_mut()))
+        {
+            *matrix = cascade.view_projection().into();
+            *split = cascade.far;
+        }
+
+        CascadeParams {
+            view_proj,
+            splits,
+            info: [
+                cascades.len() as f32,
+                self.blend,
+                1.0 / self.cascade_count as f32,
+                0.0,
+            ],
+        }
+    }
+}
+
+pub fn split_distances(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    // the "practical split scheme": a blend between even and logarithmic
+    // splits. returns count + 1 distances, starting at near and ending at far.
+    (0..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let linear = near + (far - near) * fraction;
+            let log = near * (far / near).powf(fraction);
+
+            log * lambda + linear * (1.0 - lambda)
+        })
+        .collect()
+}
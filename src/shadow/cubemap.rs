@@ -0,0 +1,280 @@
+use render_engine::collection::Data;
+use render_engine::object::Object;
+
+use vulkano::command_buffer::DynamicState;
+
+use nalgebra_glm::*;
+
+use super::dynamic_state_for_bounds;
+use crate::culling::{cull_objects, Aabb, Frustum};
+use crate::projection::{perspective_vk, DepthMode};
+
+// how the 6 faces of a cubemap are packed into one 2D texture. faces are always
+// in the order +x, -x, +y, -y, +z, -z, filling rows left to right, top to
+// bottom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CubemapLayout {
+    Row6x1,
+    Grid3x2,
+    Grid2x3,
+}
+
+impl CubemapLayout {
+    // columns, rows
+    pub fn grid(&self) -> [u32; 2] {
+        match self {
+            CubemapLayout::Row6x1 => [6, 1],
+            CubemapLayout::Grid3x2 => [3, 2],
+            CubemapLayout::Grid2x3 => [2, 3],
+        }
+    }
+
+    pub fn cell(&self, face: usize) -> [u32; 2] {
+        let columns = self.grid()[0] as usize;
+
+        [(face % columns) as u32, (face / columns) as u32]
+    }
+}
+
+impl Default for CubemapLayout {
+    fn default() -> Self {
+        CubemapLayout::Row6x1
+    }
+}
+
+// a point light's shadow cubemap, packed into a 2D texture since
+// render-engine has no cube images. each face is rendered by its own copy of
+// the caster objects with a viewport covering that face's cell.
+//
+// faces are rendered slightly wider than 90 degrees so that there are
+// `padding` texels of overlap around every edge. the shader only samples the
+// inner part of each cell, so filtering near a seam never reads from the
+// neighbouring face.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowCubemap {
+    pub layout: CubemapLayout,
+    // size of one face in texels, padding included
+    pub face_size: u32,
+    pub near: f32,
+    pub far: f32,
+    // texels on each side of a face that belong to the overlap
+    pub padding: u32,
+    // how much further than the stored depth, in world units, a receiver
+    // has to be to count as shadowed
+    pub bias: f32,
+}
+
+// one face of a ShadowCubemap, with everything needed to render to it
+#[derive(Debug, Clone)]
+pub struct CubeFace {
+    // 0..6, in the order +x, -x, +y, -y, +z, -z
+    pub index: usize,
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub dynamic_state: DynamicState,
+}
+
+// what a shader needs to turn a direction from the light into uv coordinates
+// in the packed texture and a stored depth back into a distance. see
+// l_to_shadow_map_uv and the ShadowParams block in
+// shaders/pretty/all_frag.glsl. casters and receivers both read it, so they
+// can't disagree about far or the layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubemapParams {
+    // columns, rows, uv scale, uv offset. uv scale and offset map a face's
+    // 0..1 uv to the part of its cell inside the padding.
+    pub layout: [f32; 4],
+    // near, far, bias, unused
+    pub planes: [f32; 4],
+}
+
+impl Data for CubemapParams {}
+
+impl Default for ShadowCubemap {
+    fn default() -> Self {
+        // 4 texels of padding on a 1024 face gives the 0.9921875 and
+        // 0.00390625 the shaders used to hardcode
+        Self {
+            layout: CubemapLayout::Row6x1,
+            face_size: 1024,
+            near: 1.0,
+            far: 250.0,
+            padding: 4,
+            bias: 0.05,
+        }
+    }
+}
+
+impl ShadowCubemap {
+    pub fn new(face_size: u32) -> Self {
+        Self {
+            face_size,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_layout(mut self, layout: CubemapLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        // the padding can't eat the whole face
+        self.padding = padding.min(self.face_size.saturating_sub(1) / 2);
+        self
+    }
+
+    // size of the texture to create for the whole cubemap
+    pub fn dimensions(&self) -> [u32; 2] {
+        let [columns, rows] = self.layout.grid();
+
+        [columns * self.face_size, rows * self.face_size]
+    }
+
+    pub fn fov(&self) -> f32 {
+        // the inner face_size - 2 * padding texels have to cover exactly 90
+        // degrees, so the whole face covers a bit more
+        let inner = (self.face_size - 2 * self.padding) as f32;
+
+        2.0 * (self.face_size as f32 / inner).atan()
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        // y points down in the faces like it does on screen, see
+        // l_to_shadow_map_uv
+        perspective_vk(1.0, self.fov(), self.near, self.far, DepthMode::Standard)
+    }
+
+    pub fn face_viewport(&self, face: usize) -> DynamicState {
+        let [column, row] = self.layout.cell(face);
+        let size = self.face_size as f32;
+
+        dynamic_state_for_bounds([column as f32 * size, row as f32 * size], [size, size])
+    }
+
+    pub fn faces(&self, light_pos: Vec3) -> Vec<CubeFace> {
+        // l_to_shadow_map_uv depends on these exact up vectors, don't change
+        // them without changing it too
+        let view_directions = [
+            vec3(1.0, 0.0, 0.0),
+            vec3(-1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 0.0, -1.0),
+        ];
+
+        let up_directions = [
+            vec3(0.0, -1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 0.0, -1.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+        ];
+
+        let proj = self.projection();
+
+        view_directions
+            .iter()
+            .zip(&up_directions)
+            .enumerate()
+            .map(|(index, (dir, up))| CubeFace {
+                index,
+                view: look_at(&light_pos, &(light_pos + dir), up),
+                proj,
+                dynamic_state: self.face_viewport(index),
+            })
+            .collect()
+    }
+
+    pub fn casters<C, F>(
+        &self,
+        base_object: &Object<()>,
+        light_pos: Vec3,
+        mut collection: F,
+    ) -> Vec<Object<C>>
+    where
+        F: FnMut(&CubeFace) -> C,
+    {
+        // turns one object into 6, one for each face. the object's shaders
+        // decide what goes in the collection, so the caller builds it from
+        // each face's matrices.
+        self.faces(light_pos)
+            .iter()
+            .map(|face| Object {
+                pipeline_spec: base_object.pipeline_spec.clone(),
+                vbuf: base_object.vbuf.clone(),
+                ibuf: base_object.ibuf.clone(),
+                collection: collection(face),
+                custom_dynamic_state: Some(face.dynamic_state.clone()),
+            })
+            .collect()
+    }
+
+    // what a face can see, for culling
+    pub fn face_frustum(&self, face: &CubeFace) -> Frustum {
+        Frustum::from_matrix(&(face.proj * face.view))
+    }
+
+    // like casters, but for a list of objects with world space bounds, and
+    // each face only gets the objects inside its frustum. most objects end up
+    // on one or two faces instead of all six, and anything past far on none.
+    pub fn culled_casters<C, F>(
+        &self,
+        base_objects: &[Object<()>],
+        bounds: &[Aabb],
+        light_pos: Vec3,
+        mut collection: F,
+    ) -> Vec<Object<C>>
+    where
+        C: Clone,
+        F: FnMut(&CubeFace) -> C,
+    {
+        self.faces(light_pos)
+            .iter()
+            .flat_map(|face| {
+                let visible = cull_objects(&self.face_frustum(face), base_objects, bounds).visible;
+                let face_collection = collection(face);
+
+                visible
+                    .into_iter()
+                    .map(|base_object| Object {
+                        pipeline_spec: base_object.pipeline_spec.clone(),
+                        vbuf: base_object.vbuf.clone(),
+                        ibuf: base_object.ibuf.clone(),
+                        collection: face_collection.clone(),
+                        custom_dynamic_state: Some(face.dynamic_state.clone()),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn shader_params(&self) -> CubemapParams {
+        let [columns, rows] = self.layout.grid();
+        let size = self.face_size as f32;
+        let padding = self.padding as f32;
+
+        CubemapParams {
+            layout: [
+                columns as f32,
+                rows as f32,
+                (size - 2.0 * padding) / size,
+                padding / size,
+            ],
+            planes: [self.near, self.far, self.bias, 0.0],
+        }
+    }
+}
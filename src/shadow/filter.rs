@@ -0,0 +1,236 @@
+use render_engine::collection::Data;
+use render_engine::{Device, Format, RenderPass};
+
+use std::sync::Arc;
+
+// how shadow maps are sampled. Hard, Pcf and Pcss compare against a plain
+// depth map. Vsm and Esm store something that can be blurred instead (depth
+// moments or an exponential of the depth), so their casters render to a color
+// image and there's a blur pass between casting and lighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowFilter {
+    // one comparison, blocky edges
+    Hard,
+    // percentage closer filtering: the fraction of a Poisson disk of
+    // comparisons that pass
+    Pcf,
+    // percentage closer soft shadows: PCF with a radius that grows with the
+    // distance between caster and receiver, so contact shadows stay sharp
+    Pcss,
+    // variance shadow maps, Chebyshev's inequality on blurred depth and
+    // depth squared
+    Vsm,
+    // exponential shadow maps, exp(c * depth) blurred
+    Esm,
+}
+
+impl ShadowFilter {
+    pub const ALL: [ShadowFilter; 5] = [
+        ShadowFilter::Hard,
+        ShadowFilter::Pcf,
+        ShadowFilter::Pcss,
+        ShadowFilter::Vsm,
+        ShadowFilter::Esm,
+    ];
+
+    // for cycling through them at runtime
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&f| f == self).unwrap_or(0);
+
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShadowFilter::Hard => "hard",
+            ShadowFilter::Pcf => "PCF",
+            ShadowFilter::Pcss => "PCSS",
+            ShadowFilter::Vsm => "VSM",
+            ShadowFilter::Esm => "ESM",
+        }
+    }
+
+    // true if casters write to a color image that gets blurred, false if the
+    // depth buffer is the shadow map
+    pub fn uses_moments(&self) -> bool {
+        match self {
+            ShadowFilter::Vsm | ShadowFilter::Esm => true,
+            _ => false,
+        }
+    }
+
+    // format of the image the lighting pass samples
+    pub fn map_format(&self) -> Format {
+        match self {
+            ShadowFilter::Vsm => Format::R32G32Sfloat,
+            ShadowFilter::Esm => Format::R32Sfloat,
+            _ => Format::D32Sfloat,
+        }
+    }
+
+    // the number the shaders switch on, see ShadowFilterParams
+    fn mode(&self) -> f32 {
+        match self {
+            ShadowFilter::Hard => 0.0,
+            ShadowFilter::Pcf => 1.0,
+            ShadowFilter::Pcss => 2.0,
+            ShadowFilter::Vsm => 3.0,
+            ShadowFilter::Esm => 4.0,
+        }
+    }
+}
+
+impl Default for ShadowFilter {
+    fn default() -> Self {
+        ShadowFilter::Pcf
+    }
+}
+
+// a ShadowFilter and everything that tunes it. the fields for other filters
+// are ignored, so switching back and forth keeps each filter's settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowFiltering {
+    pub filter: ShadowFilter,
+    // how many taps PCF and PCSS take, up to 32
+    pub samples: u32,
+    // PCF's radius in texels
+    pub radius: f32,
+    // PCSS's light size in texels: how far it searches for blockers, and how
+    // wide the penumbra gets
+    pub light_size: f32,
+    // VSM: variance never goes under this, or flat surfaces shadow themselves
+    pub min_variance: f32,
+    // VSM: 0..1, cuts off the faint light that leaks through overlapping
+    // casters at the cost of thinner penumbras
+    pub bleed_reduction: f32,
+    // ESM: higher is sharper and leaks less, but exp overflows an f32 past
+    // about 88
+    pub exponent: f32,
+}
+
+impl Default for ShadowFiltering {
+    fn default() -> Self {
+        Self {
+            filter: ShadowFilter::default(),
+            samples: 16,
+            radius: 1.5,
+            light_size: 12.0,
+            min_variance: 0.000_002,
+            bleed_reduction: 0.3,
+            exponent: 80.0,
+        }
+    }
+}
+
+// see the ShadowFilter block in shaders/pretty/all_frag.glsl
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowFilterParams {
+    // mode (0 hard, 1 PCF, 2 PCSS, 3 VSM, 4 ESM), sample count, PCF radius,
+    // PCSS light size
+    pub mode: [f32; 4],
+    // min variance, bleed reduction, ESM exponent, unused
+    pub params: [f32; 4],
+}
+
+impl Data for ShadowFilterParams {}
+
+impl ShadowFiltering {
+    pub fn new(filter: ShadowFilter) -> Self {
+        Self {
+            filter,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1).min(32);
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn with_light_size(mut self, light_size: f32) -> Self {
+        self.light_size = light_size;
+        self
+    }
+
+    pub fn with_min_variance(mut self, min_variance: f32) -> Self {
+        self.min_variance = min_variance;
+        self
+    }
+
+    pub fn with_bleed_reduction(mut self, bleed_reduction: f32) -> Self {
+        self.bleed_reduction = bleed_reduction.max(0.0).min(0.99);
+        self
+    }
+
+    pub fn with_exponent(mut self, exponent: f32) -> Self {
+        self.exponent = exponent.max(1.0).min(88.0);
+        self
+    }
+
+    pub fn shader_params(&self) -> ShadowFilterParams {
+        ShadowFilterParams {
+            mode: [
+                self.filter.mode(),
+                self.samples as f32,
+                self.radius,
+                self.light_size,
+            ],
+            params: [self.min_variance, self.bleed_reduction, self.exponent, 0.0],
+        }
+    }
+}
+
+// render-engine's passes only have 8 bit color attachments, which can't hold
+// depths. casters for Vsm and Esm render to this instead: a float color image
+// and a depth buffer to keep the nearest caster.
+pub fn moments_pass(device: Device, format: Format) -> RenderPass {
+    Arc::new(
+        vulkano::single_pass_renderpass!(device,
+            attachments: {
+                moments: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D32Sfloat,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [moments],
+                depth_stencil: {depth}
+            }
+        )
+        .unwrap(),
+    )
+}
+
+// and the blur pass runs in this, it doesn't need depth
+pub fn moments_blur_pass(device: Device, format: Format) -> RenderPass {
+    Arc::new(
+        vulkano::single_pass_renderpass!(device,
+            attachments: {
+                moments: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [moments],
+                depth_stencil: {}
+            }
+        )
+        .unwrap(),
+    )
+}
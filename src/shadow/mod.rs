@@ -0,0 +1,33 @@
+use vulkano::command_buffer::DynamicState;
+use vulkano::pipeline::viewport::Viewport;
+
+pub mod atlas;
+pub mod cache;
+pub mod cascades;
+pub mod cubemap;
+pub mod filter;
+pub mod spot;
+
+pub use atlas::{screen_coverage, AtlasTile, ShadowAtlas, TileRequest};
+pub use cache::{CasterState, ShadowCache};
+pub use cascades::{Cascade, CascadeParams, CascadedShadowMap};
+pub use cubemap::{CubeFace, CubemapLayout, CubemapParams, ShadowCubemap};
+pub use filter::{
+    moments_blur_pass, moments_pass, ShadowFilter, ShadowFilterParams, ShadowFiltering,
+};
+pub use spot::{SpotShadow, SpotShadowMaps, SpotShadowParams};
+
+pub fn dynamic_state_for_bounds(origin: [f32; 2], dimensions: [f32; 2]) -> DynamicState {
+    // render-engine doesn't support geometry shaders or layered rendering, so
+    // anything that draws to part of a shadow texture does it by overriding
+    // the viewport
+    DynamicState {
+        line_width: None,
+        viewports: Some(vec![Viewport {
+            origin,
+            dimensions,
+            depth_range: 0.0..1.0,
+        }]),
+        scissors: None,
+    }
+}
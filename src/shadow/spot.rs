@@ -0,0 +1,210 @@
+use render_engine::collection::Data;
+use render_engine::object::Object;
+
+use vulkano::command_buffer::DynamicState;
+
+use nalgebra_glm::*;
+
+use super::atlas::{AtlasTile, ShadowAtlas};
+use super::dynamic_state_for_bounds;
+use crate::light::SpotLight;
+use crate::projection::{perspective_vk, DepthMode};
+
+// the shaders declare arrays of this size, so it's a hard limit
+pub const MAX_SPOT_SHADOWS: usize = 4;
+
+// shadow maps for spot lights. a spot light only lights a cone, so unlike a
+// point light one perspective shadow map covers everything it can reach.
+// each light gets a square patch of one shared texture, in a row, and casters
+// draw to their light's patch with a custom viewport just like the faces of a
+// ShadowCubemap. shadow_in_tile puts a light's map in a ShadowAtlas tile
+// instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotShadowMaps {
+    // how many lights there's room for, 1..=MAX_SPOT_SHADOWS
+    pub capacity: usize,
+    // size of each light's patch in texels
+    pub resolution: u32,
+    // the near plane of every light's projection, its range is the far plane
+    pub near: f32,
+    // extra angle around the outer cone, in radians, so that filtering right
+    // at the edge of the cone doesn't sample outside the patch
+    pub margin: f32,
+}
+
+// one light's shadow map
+#[derive(Debug, Clone)]
+pub struct SpotShadow {
+    pub index: usize,
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub dynamic_state: DynamicState,
+    // where the patch is in the texture, see AtlasTile::uv_transform
+    pub uv_transform: [f32; 4],
+}
+
+impl SpotShadow {
+    pub fn view_projection(&self) -> Mat4 {
+        self.proj * self.view
+    }
+}
+
+// what the shaders need to find a light's patch and sample it, see
+// spot_lit_amount in shaders/spot-shadow/frag.glsl
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotShadowParams {
+    pub view_proj: [[[f32; 4]; 4]; MAX_SPOT_SHADOWS],
+    // each patch's uv scale and offset
+    pub uv_transform: [[f32; 4]; MAX_SPOT_SHADOWS],
+    // light count, unused, unused, unused
+    pub info: [f32; 4],
+}
+
+impl Data for SpotShadowParams {}
+
+impl Default for SpotShadowMaps {
+    fn default() -> Self {
+        Self {
+            capacity: MAX_SPOT_SHADOWS,
+            resolution: 1024,
+            near: 0.1,
+            margin: 0.02,
+        }
+    }
+}
+
+impl SpotShadowMaps {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1).min(MAX_SPOT_SHADOWS),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_resolution(mut self, resolution: u32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn with_near(mut self, near: f32) -> Self {
+        self.near = near;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    // size of the texture to create for all patches
+    pub fn dimensions(&self) -> [u32; 2] {
+        [self.resolution * self.capacity as u32, self.resolution]
+    }
+
+    pub fn patch_viewport(&self, index: usize) -> DynamicState {
+        let size = self.resolution as f32;
+
+        dynamic_state_for_bounds([index as f32 * size, 0.0], [size, size])
+    }
+
+    pub fn shadow(&self, light: &SpotLight, index: usize) -> SpotShadow {
+        let (view, proj) = self.matrices(light);
+        let capacity = self.capacity as f32;
+
+        SpotShadow {
+            index,
+            view,
+            proj,
+            dynamic_state: self.patch_viewport(index),
+            uv_transform: [1.0 / capacity, 1.0, index as f32 / capacity, 0.0],
+        }
+    }
+
+    pub fn shadow_in_tile(
+        &self,
+        light: &SpotLight,
+        index: usize,
+        tile: &AtlasTile,
+        atlas: &ShadowAtlas,
+    ) -> SpotShadow {
+        // resolution and capacity don't matter here, the tile decides both
+        let (view, proj) = self.matrices(light);
+
+        SpotShadow {
+            index,
+            view,
+            proj,
+            dynamic_state: tile.viewport(),
+            uv_transform: tile.uv_transform(atlas),
+        }
+    }
+
+    fn matrices(&self, light: &SpotLight) -> (Mat4, Mat4) {
+        let direction = normalize(&light.direction);
+        // look_at can't use an up vector parallel to the view direction
+        let up = if direction.y.abs() > 0.99 {
+            vec3(1.0, 0.0, 0.0)
+        } else {
+            vec3(0.0, 1.0, 0.0)
+        };
+
+        // SpotLight keeps the cone under 90 degrees, but the margin could
+        // still push it over
+        let fov = ((light.outer_angle + self.margin) * 2.0).min(std::f32::consts::PI - 0.01);
+
+        (
+            look_at(&light.position, &(light.position + direction), &up),
+            perspective_vk(1.0, fov, self.near, light.range, DepthMode::Standard),
+        )
+    }
+
+    pub fn shadows(&self, lights: &[SpotLight]) -> Vec<SpotShadow> {
+        // lights past the capacity don't get a shadow map
+        lights
+            .iter()
+            .take(self.capacity)
+            .enumerate()
+            .map(|(index, light)| self.shadow(light, index))
+            .collect()
+    }
+
+    pub fn casters<C, F>(
+        &self,
+        base_object: &Object<()>,
+        shadows: &[SpotShadow],
+        mut collection: F,
+    ) -> Vec<Object<C>>
+    where
+        F: FnMut(&SpotShadow) -> C,
+    {
+        // one copy of the object per light, same as ShadowCubemap::casters
+        shadows
+            .iter()
+            .map(|shadow| Object {
+                pipeline_spec: base_object.pipeline_spec.clone(),
+                vbuf: base_object.vbuf.clone(),
+                ibuf: base_object.ibuf.clone(),
+                collection: collection(shadow),
+                custom_dynamic_state: Some(shadow.dynamic_state.clone()),
+            })
+            .collect()
+    }
+
+    pub fn shader_params(&self, shadows: &[SpotShadow]) -> SpotShadowParams {
+        let mut view_proj = [[[0.0; 4]; 4]; MAX_SPOT_SHADOWS];
+        let mut uv_transform = [[0.0; 4]; MAX_SPOT_SHADOWS];
+        for (shadow, (matrix, uv)) in shadows
+            .iter()
+            .zip(view_proj.iter_mut().zip(uv_transform.iter_mut()))
+        {
+            *matrix = shadow.view_projection().into();
+            *uv = shadow.uv_transform;
+        }
+
+        SpotShadowParams {
+            view_proj,
+            uv_transform,
+            info: [shadows.len().min(MAX_SPOT_SHADOWS) as f32, 0.0, 0.0, 0.0],
+        }
+    }
+}
@@ -0,0 +1,894 @@
+/*
+Compressed textures: readers for KTX2 and DDS containers holding BC1, BC3, BC5
+or BC7 data, plus a CPU decoder for devices that can't sample BC formats
+directly (software Vulkan implementations, mostly).
+ */
+
+use render_engine::{Format, Image, Queue};
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::image::{Dimensions, ImageLayout, ImageUsage, ImmutableImage, MipmapsCount};
+use vulkano::sync::GpuFuture;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::LoadError;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockFormat {
+    Bc1,
+    Bc3,
+    Bc5,
+    Bc7,
+}
+
+impl BlockFormat {
+    pub fn block_size(&self) -> usize {
+        // size in bytes of one 4x4 block
+        match self {
+            BlockFormat::Bc1 => 8,
+            _ => 16,
+        }
+    }
+
+    pub fn vulkan_format(&self, srgb: bool) -> Format {
+        // BC5 only has two channels, so it's never sRGB
+        match (self, srgb) {
+            (BlockFormat::Bc1, false) => Format::BC1_RGBAUnormBlock,
+            (BlockFormat::Bc1, true) => Format::BC1_RGBASrgbBlock,
+            (BlockFormat::Bc3, false) => Format::BC3UnormBlock,
+            (BlockFormat::Bc3, true) => Format::BC3SrgbBlock,
+            (BlockFormat::Bc5, _) => Format::BC5UnormBlock,
+            (BlockFormat::Bc7, false) => Format::BC7UnormBlock,
+            (BlockFormat::Bc7, true) => Format::BC7SrgbBlock,
+        }
+    }
+
+    pub fn level_size(&self, dimensions: [u32; 2]) -> usize {
+        let blocks_x = ((dimensions[0] + 3) / 4).max(1) as usize;
+        let blocks_y = ((dimensions[1] + 3) / 4).max(1) as usize;
+        blocks_x * blocks_y * self.block_size()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressedTexture {
+    pub format: BlockFormat,
+    // whether the container says the data is sRGB-encoded. DDS files using
+    // legacy FourCCs don't say, so this will be false for those.
+    pub srgb: bool,
+    pub dimensions: [u32; 2],
+    // one entry per mip level, largest first
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedTexture {
+    pub fn level_dimensions(&self, level: usize) -> [u32; 2] {
+        mip_dimensions(self.dimensions, level)
+    }
+
+    pub fn decode_rgba8(&self) -> Vec<Vec<u8>> {
+        // decodes every mip level to tightly packed RGBA8
+        self.levels
+            .iter()
+            .enumerate()
+            .map(|(idx, data)| decode_level(self.format, self.level_dimensions(idx), data))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum ContainerError {
+    Io(io::Error),
+    InvalidHeader(&'static str),
+    UnsupportedFormat(String),
+    Truncated,
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerError::Io(e) => write!(f, "couldn't read texture: {}", e),
+            ContainerError::InvalidHeader(what) => write!(f, "invalid header: {}", what),
+            ContainerError::UnsupportedFormat(what) => write!(f, "unsupported format: {}", what),
+            ContainerError::Truncated => write!(f, "file is shorter than its header says"),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+impl From<io::Error> for ContainerError {
+    fn from(e: io::Error) -> Self {
+        ContainerError::Io(e)
+    }
+}
+
+pub fn is_compressed_container(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("ktx2") || ext.eq_ignore_ascii_case("dds"),
+        None => false,
+    }
+}
+
+pub fn read_container(path: &Path) -> Result<CompressedTexture, LoadError> {
+    // picks the reader based on the file's magic number rather than its
+    // extension
+    let bytes = fs::read(path).map_err(|e| LoadError::from_io(path, e))?;
+
+    if bytes.starts_with(&KTX2_IDENTIFIER) {
+        parse_ktx2(&bytes)
+    } else if bytes.starts_with(&DDS_MAGIC) {
+        parse_dds(&bytes)
+    } else {
+        Err(ContainerError::InvalidHeader("not a KTX2 or DDS file"))
+    }
+    .map_err(|e| LoadError::from_container(path, e))
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> Result<CompressedTexture, ContainerError> {
+    // header layout: https://github.khronos.org/KTX-Specification/
+    if !bytes.starts_with(&KTX2_IDENTIFIER) {
+        return Err(ContainerError::InvalidHeader("bad KTX2 identifier"));
+    }
+
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+    check_level_count(level_count, [width, height])?;
+
+    if depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err(ContainerError::UnsupportedFormat(
+            "only single 2D textures are supported".to_string(),
+        ));
+    }
+    if supercompression != 0 {
+        return Err(ContainerError::UnsupportedFormat(format!(
+            "KTX2 supercompression scheme {}",
+            supercompression
+        )));
+    }
+
+    let (format, srgb) = match vk_format {
+        // VK_FORMAT_BC1_RGB(A)_UNORM/SRGB_BLOCK
+        131 | 133 => (BlockFormat::Bc1, false),
+        132 | 134 => (BlockFormat::Bc1, true),
+        137 => (BlockFormat::Bc3, false),
+        138 => (BlockFormat::Bc3, true),
+        141 => (BlockFormat::Bc5, false),
+        145 => (BlockFormat::Bc7, false),
+        146 => (BlockFormat::Bc7, true),
+        other => {
+            return Err(ContainerError::UnsupportedFormat(format!(
+                "VkFormat {}",
+                other
+            )))
+        }
+    };
+
+    // the level index starts right after the 80 byte header + index section
+    let dimensions = [width, height];
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            let entry = 80 + level * 24;
+            let offset = read_u64(bytes, entry)? as usize;
+            let length = read_u64(bytes, entry + 8)? as usize;
+
+            let expected = format.level_size(mip_dimensions(dimensions, level));
+            if length < expected {
+                return Err(ContainerError::Truncated);
+            }
+
+            // offsets come straight from the file, so they can be anything
+            let end = offset
+                .checked_add(expected)
+                .ok_or(ContainerError::Truncated)?;
+
+            bytes
+                .get(offset..end)
+                .map(|data| data.to_vec())
+                .ok_or(ContainerError::Truncated)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CompressedTexture {
+        format,
+        srgb,
+        dimensions,
+        levels,
+    })
+}
+
+pub fn parse_dds(bytes: &[u8]) -> Result<CompressedTexture, ContainerError> {
+    // header layout: https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dds-header
+    if !bytes.starts_with(&DDS_MAGIC) {
+        return Err(ContainerError::InvalidHeader("bad DDS magic number"));
+    }
+    if read_u32(bytes, 4)? != 124 {
+        return Err(ContainerError::InvalidHeader("DDS header size isn't 124"));
+    }
+
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let level_count = read_u32(bytes, 28)?.max(1);
+    check_level_count(level_count, [width, height])?;
+    let four_cc = bytes.get(84..88).ok_or(ContainerError::Truncated)?;
+
+    // DX10 files have an extra 20 byte header with a DXGI format in it
+    let (format, srgb, mut offset) = match four_cc {
+        b"DXT1" => (BlockFormat::Bc1, false, 128),
+        b"DXT5" => (BlockFormat::Bc3, false, 128),
+        b"ATI2" | b"BC5U" => (BlockFormat::Bc5, false, 128),
+        b"DX10" => {
+            let (format, srgb) = match read_u32(bytes, 128)? {
+                71 => (BlockFormat::Bc1, false),
+                72 => (BlockFormat::Bc1, true),
+                77 => (BlockFormat::Bc3, false),
+                78 => (BlockFormat::Bc3, true),
+                83 => (BlockFormat::Bc5, false),
+                98 => (BlockFormat::Bc7, false),
+                99 => (BlockFormat::Bc7, true),
+                other => {
+                    return Err(ContainerError::UnsupportedFormat(format!(
+                        "DXGI format {}",
+                        other
+                    )))
+                }
+            };
+            // cubemaps set DDS_RESOURCE_MISC_TEXTURECUBE in miscFlag and
+            // count their faces separately from arraySize
+            if read_u32(bytes, 136)? & 0x4 != 0 {
+                return Err(ContainerError::UnsupportedFormat(
+                    "cubemaps aren't supported".to_string(),
+                ));
+            }
+            if read_u32(bytes, 140)? > 1 {
+                return Err(ContainerError::UnsupportedFormat(
+                    "texture arrays aren't supported".to_string(),
+                ));
+            }
+            (format, srgb, 148)
+        }
+        other => {
+            return Err(ContainerError::UnsupportedFormat(format!(
+                "FourCC {:?}",
+                String::from_utf8_lossy(other)
+            )))
+        }
+    };
+
+    // mip levels are stored one after another, largest first
+    let dimensions = [width, height];
+    let mut levels = vec![];
+    for level in 0..level_count as usize {
+        let size = format.level_size(mip_dimensions(dimensions, level));
+        let end = offset.checked_add(size).ok_or(ContainerError::Truncated)?;
+        let data = bytes.get(offset..end).ok_or(ContainerError::Truncated)?;
+        levels.push(data.to_vec());
+        offset += size;
+    }
+
+    Ok(CompressedTexture {
+        format,
+        srgb,
+        dimensions,
+        levels,
+    })
+}
+
+pub fn load_compressed_texture(
+    queue: Queue,
+    path: &Path,
+    srgb: bool,
+) -> Result<Image, LoadError> {
+    // loads a KTX2 or DDS file, uploading the blocks directly if the device
+    // can sample BC formats and decoding them on the CPU otherwise. srgb
+    // overrides whatever the container says, because legacy DDS files can't
+    // say anything at all.
+    let texture = read_container(path)?;
+
+    upload_compressed(queue, &texture, srgb)
+}
+
+pub fn upload_compressed(
+    queue: Queue,
+    texture: &CompressedTexture,
+    srgb: bool,
+) -> Result<Image, LoadError> {
+    upload_compressed_measured(queue, texture, srgb).map(|(image, _bytes)| image)
+}
+
+fn upload_compressed_measured(
+    queue: Queue,
+    texture: &CompressedTexture,
+    srgb: bool,
+) -> Result<(Image, usize), LoadError> {
+    let (format, levels) = if bc_supported(&queue) {
+        (texture.format.vulkan_format(srgb), texture.levels.clone())
+    } else if srgb {
+        (Format::R8G8B8A8Srgb, texture.decode_rgba8())
+    } else {
+        (Format::R8G8B8A8Unorm, texture.decode_rgba8())
+    };
+    let bytes = levels.iter().map(|level| level.len()).sum();
+
+    let image = upload_levels(queue, dimensions_2d(texture.dimensions), format, &levels)?;
+
+    Ok((image, bytes))
+}
+
+pub fn bc_supported(queue: &Queue) -> bool {
+    queue.device().enabled_features().texture_compression_bc
+}
+
+pub fn load_texture_file(queue: Queue, path: &Path, format: Format) -> Result<Image, LoadError> {
+    // like render_engine's load_texture, but returns an error instead of
+    // panicking and also understands compressed containers. format is only
+    // used to decide between sRGB and linear for those.
+    load_texture_file_measured(queue, path, format).map(|(image, _bytes)| image)
+}
+
+pub fn load_texture_file_measured(
+    queue: Queue,
+    path: &Path,
+    format: Format,
+) -> Result<(Image, usize), LoadError> {
+    // same as load_texture_file, but also returns how many bytes were
+    // uploaded to the GPU
+    if is_compressed_container(path) {
+        let texture = read_container(path)?;
+        return upload_compressed_measured(queue, &texture, is_srgb(format));
+    }
+
+    let image = image::open(path)
+        .map_err(|e| LoadError::from_image(path, e))?
+        .to_rgba();
+    let (width, height) = image.dimensions();
+    let pixels = image.into_raw();
+    let bytes = pixels.len();
+
+    let image = upload_levels(queue, dimensions_2d([width, height]), format, &[pixels])?;
+
+    Ok((image, bytes))
+}
+
+pub(crate) fn dimensions_2d(dimensions: [u32; 2]) -> Dimensions {
+    Dimensions::Dim2d {
+        width: dimensions[0],
+        height: dimensions[1],
+    }
+}
+
+fn is_srgb(format: Format) -> bool {
+    match format {
+        Format::R8G8B8A8Srgb | Format::B8G8R8A8Srgb => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn upload_levels(
+    queue: Queue,
+    dimensions: Dimensions,
+    format: Format,
+    levels: &[Vec<u8>],
+) -> Result<Image, LoadError> {
+    // creates an immutable image with one mip level per entry in levels and
+    // copies each one in with a separate buffer. for cubemaps and arrays, each
+    // level holds every layer one after another. fails if the device doesn't
+    // like the size, format or number of levels.
+    let device = queue.device().clone();
+    let usage = ImageUsage {
+        transfer_destination: true,
+        sampled: true,
+        ..ImageUsage::none()
+    };
+
+    let (image, init) = ImmutableImage::uninitialized(
+        device.clone(),
+        dimensions,
+        format,
+        MipmapsCount::Specific(levels.len() as u32),
+        usage,
+        ImageLayout::ShaderReadOnlyOptimal,
+        Some(queue.family()),
+    )
+    .map_err(|e| LoadError::Upload(e.to_string()))?;
+    let init = Arc::new(init);
+
+    let mut command_buffer = AutoCommandBufferBuilder::new(device.clone(), queue.family()).unwrap();
+    for (level, data) in levels.iter().enumerate() {
+        let level_dims = mip_dimensions([dimensions.width(), dimensions.height()], level);
+        let buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_source(),
+            data.iter().cloned(),
+        )
+        .unwrap();
+
+        command_buffer = command_buffer
+            .copy_buffer_to_image_dimensions(
+                buffer,
+                init.clone(),
+                [0, 0, 0],
+                [level_dims[0], level_dims[1], 1],
+                0,
+                dimensions.array_layers(),
+                level as u32,
+            )
+            .unwrap();
+    }
+
+    command_buffer
+        .build()
+        .unwrap()
+        .execute(queue.clone())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    Ok(image)
+}
+
+pub(crate) fn mip_dimensions(dimensions: [u32; 2], level: usize) -> [u32; 2] {
+    [
+        (dimensions[0] >> level).max(1),
+        (dimensions[1] >> level).max(1),
+    ]
+}
+
+fn check_level_count(level_count: u32, dimensions: [u32; 2]) -> Result<(), ContainerError> {
+    // a full mip chain ends at 1x1, which is floor(log2(largest side)) + 1
+    // levels. any more and the shifts in mip_dimensions overflow.
+    let max_levels = 32 - dimensions[0].max(dimensions[1]).leading_zeros();
+
+    if level_count > max_levels {
+        Err(ContainerError::InvalidHeader(
+            "more mip levels than the texture's size allows",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ContainerError> {
+    let b = bytes
+        .get(offset..offset + 4)
+        .ok_or(ContainerError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ContainerError> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(low | (high << 32))
+}
+
+// CPU decoding
+
+pub fn decode_level(format: BlockFormat, dimensions: [u32; 2], data: &[u8]) -> Vec<u8> {
+    // decodes one mip level into RGBA8. blocks hanging off the right or
+    // bottom edge are decoded in full and then clipped.
+    let (width, height) = (dimensions[0] as usize, dimensions[1] as usize);
+    let blocks_x = ((width + 3) / 4).max(1);
+    let blocks_y = ((height + 3) / 4).max(1);
+    let block_size = format.block_size();
+
+    let mut pixels = vec![0u8; width * height * 4];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let start = (by * blocks_x + bx) * block_size;
+            let block = &data[start..start + block_size];
+            let texels = match format {
+                BlockFormat::Bc1 => decode_bc1(block),
+                BlockFormat::Bc3 => decode_bc3(block),
+                BlockFormat::Bc5 => decode_bc5(block),
+                BlockFormat::Bc7 => decode_bc7(block),
+            };
+
+            for ty in 0..4 {
+                for tx in 0..4 {
+                    let (x, y) = (bx * 4 + tx, by * 4 + ty);
+                    if x < width && y < height {
+                        let dst = (y * width + x) * 4;
+                        pixels[dst..dst + 4].copy_from_slice(&texels[ty * 4 + tx]);
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+fn decode_bc1(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_color_block(block, true)
+}
+
+fn decode_bc3(block: &[u8]) -> [[u8; 4]; 16] {
+    // 8 bytes of alpha, then a BC1 color block that is always in 4 color mode
+    let alpha = decode_alpha_block(&block[0..8]);
+    let mut texels = decode_color_block(&block[8..16], false);
+    for (texel, a) in texels.iter_mut().zip(alpha.iter()) {
+        texel[3] = *a;
+    }
+
+    texels
+}
+
+fn decode_bc5(block: &[u8]) -> [[u8; 4]; 16] {
+    // two independent alpha-style blocks for red and green. blue is 0, the
+    // same as what the hardware returns, so shaders reading BC5 normal maps
+    // have to reconstruct z themselves.
+    let red = decode_alpha_block(&block[0..8]);
+    let green = decode_alpha_block(&block[8..16]);
+    let mut texels = [[0, 0, 0, 255]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[0] = red[i];
+        texel[1] = green[i];
+    }
+
+    texels
+}
+
+fn decode_color_block(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+
+    let mut palette = [[0u8; 4]; 4];
+    palette[0] = [e0[0], e0[1], e0[2], 255];
+    palette[1] = [e1[0], e1[1], e1[2], 255];
+    if c0 > c1 || !allow_transparent {
+        for ch in 0..3 {
+            palette[2][ch] = ((2 * e0[ch] as u32 + e1[ch] as u32) / 3) as u8;
+            palette[3][ch] = ((e0[ch] as u32 + 2 * e1[ch] as u32) / 3) as u8;
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    } else {
+        // 3 color mode: the last entry is transparent black
+        for ch in 0..3 {
+            palette[2][ch] = ((e0[ch] as u32 + e1[ch] as u32) / 2) as u8;
+        }
+        palette[2][3] = 255;
+        palette[3] = [0, 0, 0, 0];
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0u8; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 0b11) as usize];
+    }
+
+    texels
+}
+
+fn decode_alpha_block(block: &[u8]) -> [u8; 16] {
+    // the BC4 layout: 2 endpoints, then 16 3-bit indices
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut bits = 0u64;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (i * 8);
+    }
+
+    let mut alphas = [0u8; 16];
+    for (i, alpha) in alphas.iter_mut().enumerate() {
+        *alpha = palette[((bits >> (i * 3)) & 0b111) as usize];
+    }
+
+    alphas
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1F) as u32;
+    let g = ((color >> 5) & 0x3F) as u32;
+    let b = (color & 0x1F) as u32;
+
+    [
+        ((r << 3) | (r >> 2)) as u8,
+        ((g << 2) | (g >> 4)) as u8,
+        ((b << 3) | (b >> 2)) as u8,
+    ]
+}
+
+// BC7, following the format description in the D3D11 functional spec
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, index2_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, index2_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, index2_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+];
+
+// bit i is the subset of pixel i
+#[rustfmt::skip]
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+// bits 2i and 2i+1 are the subset of pixel i
+#[rustfmt::skip]
+const BC7_PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+#[rustfmt::skip]
+const BC7_ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15,  2,  8,  2,  2,  8,  8, 15,  2,  8,  2,  2,  8,  8,  2,  2,
+    15, 15,  6,  8,  2,  8, 15, 15,  2,  8,  2,  2,  2, 15, 15,  6,
+     6,  2,  6,  8, 15, 15,  2,  2, 15, 15, 15, 15, 15,  2,  2, 15,
+];
+
+#[rustfmt::skip]
+const BC7_ANCHORS_3_SECOND: [usize; 64] = [
+     3,  3, 15, 15,  8,  3, 15, 15,  8,  8,  6,  6,  6,  5,  3,  3,
+     3,  3,  8, 15,  3,  3,  6, 10,  5,  8,  8,  6,  8,  5, 15, 15,
+     8, 15,  3,  5,  6, 10,  8, 15, 15,  3, 15,  5, 15, 15, 15, 15,
+     3, 15,  5,  5,  5,  8,  5, 10,  5, 10,  8, 13, 15, 12,  3,  3,
+];
+
+#[rustfmt::skip]
+const BC7_ANCHORS_3_THIRD: [usize; 64] = [
+    15,  8,  8,  3, 15, 15,  3,  8, 15, 15, 15, 15, 15, 15, 15,  8,
+    15,  8, 15,  3, 15,  8, 15,  8,  3, 15,  6, 10, 15, 15, 10,  8,
+    15,  3, 15, 10, 10,  8,  9, 10,  6, 15,  8, 15,  3,  6,  6,  8,
+    15,  3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,  3, 15, 15,  8,
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&block[0..16]);
+        Self {
+            bits: u128::from_le_bytes(bytes),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = (self.bits >> self.position) & ((1u128 << count) - 1);
+        self.position += count;
+        value as u32
+    }
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut reader = BitReader::new(block);
+
+    // the mode is the number of zero bits before the first one
+    let mode_idx = match (0..8).find(|_| reader.read(1) == 1) {
+        Some(mode_idx) => mode_idx,
+        // reserved mode, the spec says to return transparent black
+        None => return [[0, 0, 0, 0]; 16],
+    };
+    let mode = &BC7_MODES[mode_idx];
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    // endpoints are stored channel by channel: all reds, then all greens...
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for ch in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[ch] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = reader.read(mode.alpha_bits);
+    }
+
+    // p-bits add one extra low bit to every channel of an endpoint
+    let (color_bits, alpha_bits) = if mode.endpoint_pbits || mode.shared_pbits {
+        let pbits: Vec<u32> = if mode.endpoint_pbits {
+            (0..endpoint_count).map(|_| reader.read(1)).collect()
+        } else {
+            (0..mode.subsets)
+                .flat_map(|_| {
+                    let bit = reader.read(1);
+                    vec![bit, bit]
+                })
+                .collect()
+        };
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits.iter()) {
+            for ch in 0..4 {
+                endpoint[ch] = (endpoint[ch] << 1) | pbit;
+            }
+        }
+        let alpha_bits = if mode.alpha_bits == 0 {
+            0
+        } else {
+            mode.alpha_bits + 1
+        };
+        (mode.color_bits + 1, alpha_bits)
+    } else {
+        (mode.color_bits, mode.alpha_bits)
+    };
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for ch in 0..3 {
+            endpoint[ch] = expand_bits(endpoint[ch], color_bits);
+        }
+        endpoint[3] = if alpha_bits == 0 {
+            255
+        } else {
+            expand_bits(endpoint[3], alpha_bits)
+        };
+    }
+
+    let subset_of = |pixel: usize| -> usize {
+        match mode.subsets {
+            2 => ((BC7_PARTITIONS_2[partition] >> pixel) & 1) as usize,
+            3 => ((BC7_PARTITIONS_3[partition] >> (pixel * 2)) & 0b11) as usize,
+            _ => 0,
+        }
+    };
+    let is_anchor = |pixel: usize| -> bool {
+        pixel == 0
+            || match mode.subsets {
+                2 => pixel == BC7_ANCHORS_2[partition],
+                3 => {
+                    pixel == BC7_ANCHORS_3_SECOND[partition]
+                        || pixel == BC7_ANCHORS_3_THIRD[partition]
+                }
+                _ => false,
+            }
+    };
+
+    // anchor pixels have their most significant index bit dropped, because
+    // it's always 0
+    let mut indices = [0u32; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let bits = if is_anchor(pixel) {
+            mode.index_bits - 1
+        } else {
+            mode.index_bits
+        };
+        *index = reader.read(bits);
+    }
+    let mut indices2 = [0u32; 16];
+    if mode.index2_bits > 0 {
+        for (pixel, index) in indices2.iter_mut().enumerate() {
+            let bits = if pixel == 0 {
+                mode.index2_bits - 1
+            } else {
+                mode.index2_bits
+            };
+            *index = reader.read(bits);
+        }
+    }
+
+    let mut texels = [[0u8; 4]; 16];
+    for (pixel, texel) in texels.iter_mut().enumerate() {
+        let subset = subset_of(pixel);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+
+        // modes 4 and 5 have separate indices for color and alpha, and mode 4
+        // can swap which set is which
+        let (color_index, color_index_bits, alpha_index, alpha_index_bits) =
+            if mode.index2_bits == 0 {
+                (indices[pixel], mode.index_bits, indices[pixel], mode.index_bits)
+            } else if index_selection == 0 {
+                (indices[pixel], mode.index_bits, indices2[pixel], mode.index2_bits)
+            } else {
+                (indices2[pixel], mode.index2_bits, indices[pixel], mode.index_bits)
+            };
+
+        let color_weight = bc7_weight(color_index_bits, color_index);
+        let alpha_weight = bc7_weight(alpha_index_bits, alpha_index);
+
+        let mut color = [0u8; 4];
+        for ch in 0..3 {
+            color[ch] = interpolate(e0[ch], e1[ch], color_weight);
+        }
+        color[3] = interpolate(e0[3], e1[3], alpha_weight);
+
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => {}
+        }
+
+        *texel = color;
+    }
+
+    texels
+}
+
+fn bc7_weight(bits: u32, index: u32) -> u32 {
+    match bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    }
+}
+
+fn expand_bits(value: u32, bits: u32) -> u32 {
+    // replicates the high bits into the low ones to go from n bits to 8
+    let shifted = value << (8 - bits);
+    shifted | (shifted >> bits)
+}
+
+fn interpolate(e0: u32, e1: u32, weight: u32) -> u8 {
+    (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}